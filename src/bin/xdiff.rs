@@ -1,17 +1,30 @@
 use anyhow::Result;
 use clap::{Parser, Subcommand};
+use console::style;
 use dialoguer::theme::ColorfulTheme;
 use dialoguer::Input;
 use dialoguer::MultiSelect;
-use std::io::stdout;
-use std::io::Write;
-use xdiff::cli::{parse_key_val, KeyVal};
+use indexmap::IndexMap;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::Method;
+use serde::Serialize;
+use std::fs;
+use std::io::{stdout, Write};
+use std::path::Path;
+use xdiff::cli::{
+    parse_body, parse_header, parse_key_val, parse_method, parse_request, write_item, KeyVal,
+};
 use xdiff::Args;
+use xdiff::ConfigFormat;
 use xdiff::DiffConfig;
 use xdiff::DiffItem;
+use xdiff::ItemFilter;
 use xdiff::Load;
+use xdiff::Noise;
+use xdiff::PerfContext;
 use xdiff::RequestContext;
 use xdiff::ResponseContext;
+use xdiff::Session;
 
 /// Diff two http requests and compare the difference of the responses
 #[derive(Debug, Parser)]
@@ -27,25 +40,251 @@ pub enum Action {
     /// Diff two API responses based on given profile
     Run(RunOptions),
     /// Parse URLs to generate a profile
-    Parse,
+    Parse(ParseOptions),
+    /// Diff two urls or curl commands without a profile
+    Quick(QuickOptions),
+    /// Check every item of a profile and report all the problems
+    Validate(ValidateOptions),
+    /// Print the JSON Schema of the profile, e.g. for editor completion and validation
+    Schema(SchemaOptions),
+    /// List the items of a profile with their requests
+    List(ListOptions),
+    /// Print an item after defaults, fragments and environment variables are applied
+    Show(ShowOptions),
 }
 
 #[derive(Parser, Debug)]
 pub struct RunOptions {
-    /// Item name
-    #[clap(short, long, value_parser)]
-    pub item: String,
+    #[clap(flatten)]
+    pub filter: FilterOptions,
 
     /// They are used to override the query, headers and body of the request.
-    /// For query params, use `-e key=value`
-    /// For headers, use `-e %key=value`
-    /// For body, use `-e @key=value`
-    #[clap(short, long, value_parser = parse_key_val, number_of_values = 1)]
+    /// For query params, use `-a key=value`
+    /// For headers, use `-a %key=value`
+    /// For body, use `-a @key=value`, the key can be a nested path like `@user.address.city`.
+    /// Use `key:=json` for raw json values, `key[]=value` to append to an array and
+    /// `key=@file` to read the value from a file
+    /// (`key:=@file` or a `.json` file to parse it as json).
+    /// Use `-key`, `-%key` or `-@key` to remove a configured query param, header or body field.
+    /// Prefix with `left:` or `right:` to only override the baseline or the other requests,
+    /// e.g. `-a right:%x-feature=on`
+    #[clap(
+        short,
+        long,
+        value_parser = parse_key_val,
+        number_of_values = 1,
+        allow_hyphen_values = true
+    )]
     pub args: Vec<KeyVal>,
 
     /// Configuration to use for diff
     #[clap(short, long, default_value = "fixtures/test.yaml")]
     pub config: Option<String>,
+
+    /// Format of the configuration: yaml, json or toml. Detected from the file extension by default
+    #[clap(long)]
+    pub format: Option<ConfigFormat>,
+
+    /// Keep the cookies across the items of this run, separately for every request name
+    #[clap(long)]
+    pub cookies: bool,
+
+    /// Load the cookies of a request name from a Netscape cookie file and save them back
+    /// after the run, e.g. `--cookie-jar req1=left.txt`. Implies `--cookies`
+    #[clap(long, value_parser = parse_cookie_jar, number_of_values = 1)]
+    pub cookie_jar: Vec<(String, String)>,
+
+    /// Environment of the config for req1 or the baseline request
+    #[clap(long)]
+    pub left: Option<String>,
+
+    /// Environment of the config for req2 or the requests diffed against the baseline
+    #[clap(long)]
+    pub right: Option<String>,
+}
+
+fn parse_cookie_jar(s: &str) -> Result<(String, String)> {
+    let (side, path) = s
+        .split_once('=')
+        .ok_or_else(|| anyhow::anyhow!("invalid cookie jar {}, expect name=file", s))?;
+    Ok((side.to_string(), path.to_string()))
+}
+
+#[derive(Parser, Debug)]
+pub struct ParseOptions {
+    /// Url of req1, prompted if not given
+    #[clap(long)]
+    pub url1: Option<String>,
+
+    /// Url of req2, prompted if not given
+    #[clap(long)]
+    pub url2: Option<String>,
+
+    /// Item name, prompted if not given
+    #[clap(short, long)]
+    pub name: Option<String>,
+
+    /// Method of both requests
+    #[clap(short = 'X', long, value_parser = parse_method)]
+    pub method: Option<Method>,
+
+    /// Header of both requests, e.g. `-H "authorization: Bearer token"`
+    #[clap(short = 'H', long = "header", value_parser = parse_header, number_of_values = 1)]
+    pub headers: Vec<(HeaderName, HeaderValue)>,
+
+    /// Response header to skip. The noise is detected by sending the requests if no skip lists are given
+    #[clap(long = "skip-header", number_of_values = 1)]
+    pub skip_headers: Vec<String>,
+
+    /// Response body field to skip, a dot separated path like `data.id`
+    #[clap(long = "skip-body", number_of_values = 1)]
+    pub skip_body: Vec<String>,
+
+    /// Add the item to this config instead of printing it, the file is created if missing
+    #[clap(short, long)]
+    pub output: Option<String>,
+
+    /// Overwrite an item of the same name in the output config
+    #[clap(long)]
+    pub force: bool,
+
+    /// Format of the generated profile: yaml, json or toml. Detected from the output extension by default
+    #[clap(long)]
+    pub format: Option<ConfigFormat>,
+}
+
+#[derive(Parser, Debug)]
+pub struct QuickOptions {
+    /// Url or curl command of req1, e.g. `"curl -H 'x-token: a' https://a.com/users"`
+    pub req1: String,
+
+    /// Url or curl command of req2
+    pub req2: String,
+
+    /// Method of both requests
+    #[clap(short = 'X', long, value_parser = parse_method)]
+    pub method: Option<Method>,
+
+    /// Header of both requests, e.g. `-H "authorization: Bearer token"`
+    #[clap(short = 'H', long = "header", value_parser = parse_header, number_of_values = 1)]
+    pub headers: Vec<(HeaderName, HeaderValue)>,
+
+    /// Json body of both requests, `@file` reads it from a file
+    #[clap(short = 'd', long = "data", value_parser = parse_body)]
+    pub body: Option<serde_json::Value>,
+
+    /// Response header to skip
+    #[clap(long = "skip-header", number_of_values = 1)]
+    pub skip_headers: Vec<String>,
+
+    /// Response body field to skip, a dot separated path like `data.id`
+    #[clap(long = "skip-body", number_of_values = 1)]
+    pub skip_body: Vec<String>,
+
+    /// Overrides of the requests, the same as the ones of `run`
+    #[clap(
+        short,
+        long,
+        value_parser = parse_key_val,
+        number_of_values = 1,
+        allow_hyphen_values = true
+    )]
+    pub args: Vec<KeyVal>,
+
+    /// Save the requests as an item of this config, the file is created if missing
+    #[clap(long)]
+    pub save: Option<String>,
+
+    /// Name of the saved item
+    #[clap(short, long, default_value = "quick")]
+    pub name: String,
+
+    /// Overwrite an item of the same name in the saved config
+    #[clap(long)]
+    pub force: bool,
+
+    /// Format of the saved config: yaml, json or toml. Detected from the file extension by default
+    #[clap(long)]
+    pub format: Option<ConfigFormat>,
+}
+
+#[derive(Parser, Debug)]
+pub struct ValidateOptions {
+    /// Configuration to validate
+    #[clap(short, long, default_value = "fixtures/test.yaml")]
+    pub config: String,
+
+    /// Format of the configuration: yaml, json or toml. Detected from the file extension by default
+    #[clap(long)]
+    pub format: Option<ConfigFormat>,
+}
+
+#[derive(Parser, Debug)]
+pub struct FilterOptions {
+    /// Item names or globs like `user_*`, the names are run in the given order. All items by default
+    #[clap(short, long, value_parser)]
+    pub item: Vec<String>,
+
+    /// Only select the items with one of these tags
+    #[clap(long, number_of_values = 1)]
+    pub tag: Vec<String>,
+
+    /// Skip the items with one of these tags
+    #[clap(long, number_of_values = 1)]
+    pub exclude_tag: Vec<String>,
+}
+
+impl FilterOptions {
+    fn filter(&self) -> Result<ItemFilter> {
+        ItemFilter::new(&self.item, &self.tag, &self.exclude_tag)
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct ListOptions {
+    #[clap(flatten)]
+    pub filter: FilterOptions,
+
+    /// Configuration to list
+    #[clap(short, long, default_value = "fixtures/test.yaml")]
+    pub config: String,
+
+    /// Format of the configuration: yaml, json or toml. Detected from the file extension by default
+    #[clap(long)]
+    pub format: Option<ConfigFormat>,
+
+    /// Print the items as JSON
+    #[clap(long)]
+    pub json: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct ShowOptions {
+    /// Item name
+    pub item: String,
+
+    /// Configuration of the item
+    #[clap(short, long, default_value = "fixtures/test.yaml")]
+    pub config: String,
+
+    /// Format of the configuration: yaml, json or toml. Detected from the file extension by default
+    #[clap(long)]
+    pub format: Option<ConfigFormat>,
+
+    /// Environment of the config for req1 or the baseline request
+    #[clap(long)]
+    pub left: Option<String>,
+
+    /// Environment of the config for req2 or the requests diffed against the baseline
+    #[clap(long)]
+    pub right: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct SchemaOptions {
+    /// Write the schema to this file instead of stdout
+    #[clap(short, long)]
+    pub output: Option<String>,
 }
 
 #[tokio::main]
@@ -53,67 +292,361 @@ async fn main() -> Result<()> {
     let opts = Options::parse();
     match opts.action {
         Action::Run(args) => run(args).await?,
-        Action::Parse => parse().await?,
+        Action::Parse(args) => parse(args).await?,
+        Action::Quick(args) => quick(args).await?,
+        Action::Validate(args) => validate(args)?,
+        Action::Schema(args) => schema(args)?,
+        Action::List(args) => list(args).await?,
+        Action::Show(args) => show(args).await?,
     }
     Ok(())
 }
 
-async fn parse() -> Result<()> {
+async fn parse(opts: ParseOptions) -> Result<()> {
     let theme = ColorfulTheme::default();
 
-    let url1: String = Input::with_theme(&theme)
-        .with_prompt("Enter url1")
-        .interact_text()
-        .unwrap();
-    let request1: RequestContext = url1.parse()?;
-
-    let url2: String = Input::with_theme(&theme)
-        .with_prompt("Enter url2")
-        .interact_text()
-        .unwrap();
-    let request2: RequestContext = url2.parse()?;
-
-    let name: String = Input::with_theme(&theme)
-        .with_prompt("Enter item name")
-        .interact_text()
-        .unwrap();
-
-    let res = request1.send(&Args::default()).await?;
-    let headers = res.header_keys();
-    let chosen = MultiSelect::with_theme(&theme)
-        .with_prompt("Select headers to skip")
-        .items(&headers)
+    let mut headers = HeaderMap::new();
+    headers.extend(opts.headers);
+    let request = |url: &str| -> Result<RequestContext> {
+        let req = parse_request(url)?;
+        let req = match &opts.method {
+            Some(method) => req.with_method(method.clone()),
+            None => req,
+        };
+        Ok(req.with_headers(headers.clone()))
+    };
+    let request1 = request(&prompt(&theme, opts.url1, "Enter url1")?)?;
+    let request2 = request(&prompt(&theme, opts.url2, "Enter url2")?)?;
+    let name = prompt(&theme, opts.name, "Enter item name")?;
+
+    let (skip_headers, skip_body) = if opts.skip_headers.is_empty() && opts.skip_body.is_empty() {
+        detect_noise(&theme, &request1, &request2).await?
+    } else {
+        (opts.skip_headers, opts.skip_body)
+    };
+
+    let response = ResponseContext::new(skip_headers, skip_body);
+    let item = DiffItem::new(request1, request2, response, PerfContext::default());
+    write_item(
+        &name,
+        &item,
+        opts.output.as_deref(),
+        opts.format,
+        opts.force,
+    )
+}
+
+// cargo run --bin xdiff quick https://jsonplaceholder.typicode.com/todos/1 https://jsonplaceholder.typicode.com/todos/2 --skip-body id
+async fn quick(opts: QuickOptions) -> Result<()> {
+    let mut headers = HeaderMap::new();
+    headers.extend(opts.headers);
+    let request = |s: &str| -> Result<RequestContext> {
+        let req = parse_request(s)?;
+        let req = match &opts.method {
+            Some(method) => req.with_method(method.clone()),
+            None => req,
+        };
+        let req = req.with_headers(headers.clone());
+        Ok(match &opts.body {
+            Some(body) => req.with_body(body.clone()),
+            None => req,
+        })
+    };
+    let req1 = request(&opts.req1)?;
+    let req2 = request(&opts.req2)?;
+    let response = ResponseContext::new(opts.skip_headers, opts.skip_body);
+    let item = DiffItem::new(req1, req2, response, PerfContext::default());
+    item.validate()?;
+    if let Some(path) = &opts.save {
+        write_item(&opts.name, &item, Some(path), opts.format, opts.force)?;
+    }
+
+    let result = item.diff(&opts.args.into(), &Session::new()).await?;
+    let mut stdout = stdout().lock();
+    write!(stdout, "{}", result.output)?;
+    for failure in &result.failures {
+        writeln!(stdout, "{}", style(failure).red())?;
+    }
+    if !result.is_ok() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// the value of a flag, prompted for if not given
+fn prompt(theme: &ColorfulTheme, value: Option<String>, prompt: &str) -> Result<String> {
+    match value {
+        Some(value) => Ok(value),
+        None => Ok(Input::with_theme(theme)
+            .with_prompt(prompt)
+            .interact_text()?),
+    }
+}
+
+/// send each request twice, the fields that change between identical requests are noise.
+/// The detected noise is confirmed when run in a terminal
+async fn detect_noise(
+    theme: &ColorfulTheme,
+    request1: &RequestContext,
+    request2: &RequestContext,
+) -> Result<(Vec<String>, Vec<String>)> {
+    let args = Args::default();
+    let res = request1.send(&args).await?;
+    let mut noise = Noise::detect(&res, &request1.send(&args).await?);
+    noise.merge(Noise::detect(
+        &request2.send(&args).await?,
+        &request2.send(&args).await?,
+    ));
+
+    let noisy_headers: Vec<_> = noise.headers().collect();
+    let body: Vec<_> = noise.body().map(|p| p.to_string()).collect();
+    if !console::user_attended() {
+        let headers = noisy_headers.into_iter().map(String::from).collect();
+        return Ok((headers, body));
+    }
+
+    let mut headers: Vec<_> = res
+        .header_keys()
+        .into_iter()
+        .map(|h| {
+            let checked = noisy_headers.contains(&h.as_str());
+            (h, checked)
+        })
+        .collect();
+    for h in &noisy_headers {
+        if !headers.iter().any(|(k, _)| k == h) {
+            headers.push((h.to_string(), true));
+        }
+    }
+    let skip_headers = select(theme, "Select headers to skip", &headers)?;
+
+    let body: Vec<_> = body.into_iter().map(|p| (p, true)).collect();
+    let skip_body = if body.is_empty() {
+        vec![]
+    } else {
+        select(theme, "Select body fields to skip", &body)?
+    };
+    Ok((skip_headers, skip_body))
+}
+
+fn select(theme: &ColorfulTheme, prompt: &str, items: &[(String, bool)]) -> Result<Vec<String>> {
+    let chosen = MultiSelect::with_theme(theme)
+        .with_prompt(prompt)
+        .items_checked(items)
         .interact()?;
-    let skip_headers = chosen
-        .iter()
-        .map(|i| headers[*i].to_string())
-        .collect::<Vec<_>>();
-
-    let response = ResponseContext::new(skip_headers, vec![]);
-    let item = DiffItem::new(request1, request2, response);
-    let config = DiffConfig::new(vec![(name, item)].into_iter().collect());
-    let output = serde_yaml::to_string(&config)?;
-    let after_highlight = xdiff::highlight_text(&output, "yaml");
+    Ok(chosen.iter().map(|i| items[*i].0.clone()).collect())
+}
+
+#[derive(Serialize)]
+struct ListItem<'a> {
+    name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<&'a str>,
+    tags: &'a [String],
+    requests: Vec<ListRequest<'a>>,
+}
+
+#[derive(Serialize)]
+struct ListRequest<'a> {
+    name: &'a str,
+    method: &'a str,
+    url: &'a str,
+}
+
+// cargo run --bin xdiff list --json
+async fn list(opts: ListOptions) -> Result<()> {
+    let config = DiffConfig::load(&opts.config, opts.format).await?;
+    let items = config
+        .select_items(&opts.filter.filter()?)
+        .map_err(|e| anyhow::anyhow!("xdiff {} in config file {}", e, opts.config))?;
     let mut stdout = stdout().lock();
-    write!(stdout, "\n{}", after_highlight.unwrap())?;
+    if opts.json {
+        let items: Vec<_> = items
+            .iter()
+            .map(|(name, item)| ListItem {
+                name,
+                description: item.description(),
+                tags: item.tags(),
+                requests: item
+                    .requests()
+                    .into_iter()
+                    .map(|(name, req)| ListRequest {
+                        name,
+                        method: req.method().as_str(),
+                        url: req.raw_url(),
+                    })
+                    .collect(),
+            })
+            .collect();
+        writeln!(stdout, "{}", serde_json::to_string_pretty(&items)?)?;
+        return Ok(());
+    }
+    for (name, item) in items {
+        writeln!(stdout, "{}{}", style(name).bold(), tags_text(item.tags()))?;
+        if let Some(description) = item.description() {
+            writeln!(stdout, "  {}", style(description).dim())?;
+        }
+        for (name, req) in item.requests() {
+            writeln!(stdout, "  {} {} {}", name, req.method(), req.raw_url())?;
+        }
+    }
+    Ok(())
+}
+
+/// the tags of an item after its name in the list
+fn tags_text(tags: &[String]) -> String {
+    match tags.is_empty() {
+        true => String::new(),
+        false => format!(" {}", style(format!("[{}]", tags.join(", "))).cyan()),
+    }
+}
+
+// cargo run --bin xdiff show profile --left staging --right prod
+async fn show(opts: ShowOptions) -> Result<()> {
+    let config = DiffConfig::load(&opts.config, opts.format).await?;
+    let item = config.get_item(&opts.item).ok_or_else(|| {
+        anyhow::anyhow!(
+            "xdiff item {} not found in config file {}",
+            opts.item,
+            opts.config
+        )
+    })?;
+    let left = opts
+        .left
+        .as_deref()
+        .map(|name| config.environment(name))
+        .transpose()?;
+    let right = opts
+        .right
+        .as_deref()
+        .map(|name| config.environment(name))
+        .transpose()?;
+    let item = item.with_environments(left, right)?;
+    let output = serde_yaml::to_string(&IndexMap::from([(opts.item, item)]))?;
+    write!(
+        stdout().lock(),
+        "{}",
+        xdiff::highlight_text(&output, "yaml")?
+    )?;
+    Ok(())
+}
+
+// cargo run --bin xdiff schema -o xdiff.schema.json
+fn schema(opts: SchemaOptions) -> Result<()> {
+    let output = serde_json::to_string_pretty(&DiffConfig::json_schema())?;
+    match opts.output {
+        Some(path) => fs::write(path, output + "\n")?,
+        None => writeln!(stdout().lock(), "{}", output)?,
+    }
+    Ok(())
+}
+
+// cargo run --bin xdiff validate -c fixtures/test.yaml
+fn validate(opts: ValidateOptions) -> Result<()> {
+    let problems = DiffConfig::lint(&opts.config, opts.format);
+    let mut stdout = stdout().lock();
+    for problem in &problems {
+        writeln!(stdout, "{}", style(problem).red())?;
+    }
+    if !problems.is_empty() {
+        writeln!(
+            stdout,
+            "{} problem(s) found in {}",
+            problems.len(),
+            opts.config
+        )?;
+        std::process::exit(1);
+    }
+    writeln!(stdout, "{} is valid", opts.config)?;
     Ok(())
 }
 
 // cargo run --bin xdiff run -i todo -a a=100 -a %b=1 -a @c=2
 // cargo run --bin xdiff run -i rust -a a=100 -a %b=1 -a @c=2
+// cargo run --bin xdiff run -i todo -a name=alice -a @user.age:=30 -a @tags[]=new -a @profile=@profile.json
+// cargo run --bin xdiff run -i user --left staging --right prod
+// cargo run --bin xdiff run -i todo -c fixtures/test.toml
+// cargo run --bin xdiff run -i login -i profile --cookie-jar req1=left.txt --cookie-jar req2=right.txt
 async fn run(opts: RunOptions) -> Result<()> {
     let file = opts
         .config
         .unwrap_or_else(|| "fixtures/test.yaml".to_string());
-    let config = DiffConfig::load_yaml(&file).await?;
+    let config = DiffConfig::load(&file, opts.format).await?;
 
-    let item = config.get_item(&opts.item).ok_or_else(|| {
-        anyhow::anyhow!("xdiff item {} not found in config file {}", opts.item, file)
-    })?;
+    let session = if opts.cookies || !opts.cookie_jar.is_empty() {
+        Session::with_cookies()
+    } else {
+        Session::new()
+    };
+    for (side, path) in &opts.cookie_jar {
+        if Path::new(path).exists() {
+            session.load_cookies(side, path)?;
+        }
+    }
+
+    let left = opts
+        .left
+        .as_deref()
+        .map(|name| config.environment(name))
+        .transpose()?;
+    let right = opts
+        .right
+        .as_deref()
+        .map(|name| config.environment(name))
+        .transpose()?;
+    let items = config
+        .select_items(&opts.filter.filter()?)
+        .map_err(|e| anyhow::anyhow!("xdiff {} in config file {}", e, file))?;
+    if items.is_empty() {
+        return Err(anyhow::anyhow!(
+            "no items in config file {} match the filter",
+            file
+        ));
+    }
     let args = opts.args.into();
-    let output = item.diff(args).await?;
-    let mut stdout = stdout().lock();
-    write!(stdout, "{}", output)?;
+    let mut failed = false;
+    for (name, item) in &items {
+        let item = item.with_environments(left, right)?;
+        let result = item.diff(&args, &session).await?;
+        let mut stdout = stdout().lock();
+        if items.len() > 1 {
+            writeln!(stdout, "{}", style(format!("==> {}", name)).bold())?;
+        }
+        write!(stdout, "{}", result.output)?;
+        for failure in &result.failures {
+            writeln!(stdout, "{}", style(failure).red())?;
+        }
+        failed |= !result.is_ok();
+    }
+
+    for (side, path) in &opts.cookie_jar {
+        session.save_cookies(side, path)?;
+    }
+    if failed {
+        std::process::exit(1);
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn args_should_accept_removals() {
+        let opts = Options::try_parse_from(["xdiff", "run", "-a", "-%x-token", "-a", "right:-@id"]);
+        let Action::Run(opts) = opts.unwrap().action else {
+            panic!("expected run");
+        };
+        let keys: Vec<_> = opts.args.iter().map(|a| a.key.as_str()).collect();
+        assert_eq!(keys, vec!["x-token", "id"]);
+
+        let opts = Options::try_parse_from(["xdiff", "quick", "a.com", "b.com", "-a", "-page"]);
+        let Action::Quick(opts) = opts.unwrap().action else {
+            panic!("expected quick");
+        };
+        assert_eq!(opts.args[0].key, "page");
+        assert!(opts.args[0].value.is_none());
+    }
+}
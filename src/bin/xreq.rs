@@ -1,17 +1,30 @@
 use anyhow::Result;
 use clap::{Parser, Subcommand};
+use console::style;
 use dialoguer::theme::ColorfulTheme;
 use dialoguer::Input;
-use std::io::stdout;
-use std::io::Write;
+use indexmap::IndexMap;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::Method;
+use serde::Serialize;
+use std::fs;
+use std::io::{stdout, Write};
+use std::path::Path;
 use xdiff::body_text;
-use xdiff::cli::{parse_key_val, KeyVal};
+use xdiff::cli::{parse_header, parse_key_val, parse_method, write_item, KeyVal, KeyValSide};
 use xdiff::headers_text;
 use xdiff::highlight_text;
 use xdiff::status_text;
+use xdiff::Args;
+use xdiff::Assertions;
+use xdiff::BodySchema;
+use xdiff::ConfigFormat;
+use xdiff::ItemFilter;
 use xdiff::Load;
 use xdiff::RequestConfig;
 use xdiff::RequestContext;
+use xdiff::RequestItem;
+use xdiff::Session;
 
 /// Diff two http requests and compare the difference of the responses
 #[derive(Debug, Parser)]
@@ -27,25 +40,168 @@ pub enum Action {
     /// Diff two API responses based on given profile
     Run(RunOptions),
     /// Parse URLs to generate a profile
-    Parse,
+    Parse(ParseOptions),
+    /// Check every item of a profile and report all the problems
+    Validate(ValidateOptions),
+    /// Print the JSON Schema of the profile, e.g. for editor completion and validation
+    Schema(SchemaOptions),
+    /// List the items of a profile with their requests
+    List(ListOptions),
+    /// Print an item after defaults, fragments and environment variables are applied
+    Show(ShowOptions),
 }
 
 #[derive(Parser, Debug)]
 pub struct RunOptions {
-    /// Item name
-    #[clap(short, long, value_parser)]
-    pub item: String,
+    #[clap(flatten)]
+    pub filter: FilterOptions,
 
     /// They are used to override the query, headers and body of the request.
-    /// For query params, use `-e key=value`
-    /// For headers, use `-e %key=value`
-    /// For body, use `-e @key=value`
-    #[clap(short, long, value_parser = parse_key_val, number_of_values = 1)]
+    /// For query params, use `-a key=value`
+    /// For headers, use `-a %key=value`
+    /// For body, use `-a @key=value`, the key can be a nested path like `@user.address.city`.
+    /// Use `key:=json` for raw json values, `key[]=value` to append to an array and
+    /// `key=@file` to read the value from a file
+    /// (`key:=@file` or a `.json` file to parse it as json).
+    /// Use `-key`, `-%key` or `-@key` to remove a configured query param, header or body field
+    #[clap(
+        short,
+        long,
+        value_parser = parse_arg,
+        number_of_values = 1,
+        allow_hyphen_values = true
+    )]
     pub args: Vec<KeyVal>,
 
     /// Configuration to use for diff
     #[clap(short, long, default_value = "fixtures/xreq.yaml")]
     pub config: Option<String>,
+
+    /// Format of the configuration: yaml, json or toml. Detected from the file extension by default
+    #[clap(long)]
+    pub format: Option<ConfigFormat>,
+
+    /// Keep the cookies across the items of this run
+    #[clap(long)]
+    pub cookies: bool,
+
+    /// Load the cookies from a Netscape cookie file and save them back after the run.
+    /// Implies `--cookies`
+    #[clap(long)]
+    pub cookie_jar: Option<String>,
+
+    /// Environment of the config to send the requests to
+    #[clap(long)]
+    pub env: Option<String>,
+}
+
+/// xreq has a single side, all items share one cookie jar
+const COOKIE_SIDE: &str = "default";
+
+#[derive(Parser, Debug)]
+pub struct ParseOptions {
+    /// Url of the request, prompted if not given
+    #[clap(long)]
+    pub url: Option<String>,
+
+    /// Item name, prompted if not given
+    #[clap(short, long)]
+    pub name: Option<String>,
+
+    /// Method of the request
+    #[clap(short = 'X', long, value_parser = parse_method)]
+    pub method: Option<Method>,
+
+    /// Header of the request, e.g. `-H "authorization: Bearer token"`
+    #[clap(short = 'H', long = "header", value_parser = parse_header, number_of_values = 1)]
+    pub headers: Vec<(HeaderName, HeaderValue)>,
+
+    /// Add the item to this config instead of printing it, the file is created if missing
+    #[clap(short, long)]
+    pub output: Option<String>,
+
+    /// Overwrite an item of the same name in the output config
+    #[clap(long)]
+    pub force: bool,
+
+    /// Format of the generated profile: yaml, json or toml. Detected from the output extension by default
+    #[clap(long)]
+    pub format: Option<ConfigFormat>,
+}
+
+#[derive(Parser, Debug)]
+pub struct ValidateOptions {
+    /// Configuration to validate
+    #[clap(short, long, default_value = "fixtures/xreq.yaml")]
+    pub config: String,
+
+    /// Format of the configuration: yaml, json or toml. Detected from the file extension by default
+    #[clap(long)]
+    pub format: Option<ConfigFormat>,
+}
+
+#[derive(Parser, Debug)]
+pub struct FilterOptions {
+    /// Item names or globs like `user_*`, the names are run in the given order. All items by default
+    #[clap(short, long, value_parser)]
+    pub item: Vec<String>,
+
+    /// Only select the items with one of these tags
+    #[clap(long, number_of_values = 1)]
+    pub tag: Vec<String>,
+
+    /// Skip the items with one of these tags
+    #[clap(long, number_of_values = 1)]
+    pub exclude_tag: Vec<String>,
+}
+
+impl FilterOptions {
+    fn filter(&self) -> Result<ItemFilter> {
+        ItemFilter::new(&self.item, &self.tag, &self.exclude_tag)
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct ListOptions {
+    #[clap(flatten)]
+    pub filter: FilterOptions,
+
+    /// Configuration to list
+    #[clap(short, long, default_value = "fixtures/xreq.yaml")]
+    pub config: String,
+
+    /// Format of the configuration: yaml, json or toml. Detected from the file extension by default
+    #[clap(long)]
+    pub format: Option<ConfigFormat>,
+
+    /// Print the items as JSON
+    #[clap(long)]
+    pub json: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct ShowOptions {
+    /// Item name
+    pub item: String,
+
+    /// Configuration of the item
+    #[clap(short, long, default_value = "fixtures/xreq.yaml")]
+    pub config: String,
+
+    /// Format of the configuration: yaml, json or toml. Detected from the file extension by default
+    #[clap(long)]
+    pub format: Option<ConfigFormat>,
+
+    /// Environment of the config to resolve the item in
+    #[clap(long)]
+    pub env: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct SchemaOptions {
+    /// Write the schema to this file instead of stdout
+    #[clap(short, long)]
+    pub output: Option<String>,
 }
 
 #[tokio::main]
@@ -53,46 +209,231 @@ async fn main() -> Result<()> {
     let opts = Options::parse();
     match opts.action {
         Action::Run(args) => run(args).await?,
-        Action::Parse => parse().await?,
+        Action::Parse(args) => parse(args).await?,
+        Action::Validate(args) => validate(args)?,
+        Action::Schema(args) => schema(args)?,
+        Action::List(args) => list(args).await?,
+        Action::Show(args) => show(args).await?,
     }
     Ok(())
 }
 
-async fn parse() -> Result<()> {
+async fn parse(opts: ParseOptions) -> Result<()> {
     let theme = ColorfulTheme::default();
 
-    let url: String = Input::with_theme(&theme)
-        .with_prompt("Enter url")
-        .interact_text()
-        .unwrap();
-
-    let name: String = Input::with_theme(&theme)
-        .with_prompt("Enter item name")
-        .interact_text()
-        .unwrap();
+    let url = prompt(&theme, opts.url, "Enter url")?;
+    let name = prompt(&theme, opts.name, "Enter item name")?;
 
+    let mut headers = HeaderMap::new();
+    headers.extend(opts.headers);
     let request: RequestContext = url.parse()?;
-    let config = RequestConfig::new(vec![(name, request)].into_iter().collect());
-    let output = serde_yaml::to_string(&config)?;
-    let after_highlight = xdiff::highlight_text(&output, "yaml");
+    let request = match opts.method {
+        Some(method) => request.with_method(method),
+        None => request,
+    };
+    let item = RequestItem::new(request.with_headers(headers), Assertions::default(), None);
+    write_item(
+        &name,
+        &item,
+        opts.output.as_deref(),
+        opts.format,
+        opts.force,
+    )
+}
+
+/// the value of a flag, prompted for if not given
+fn prompt(theme: &ColorfulTheme, value: Option<String>, prompt: &str) -> Result<String> {
+    match value {
+        Some(value) => Ok(value),
+        None => Ok(Input::with_theme(theme)
+            .with_prompt(prompt)
+            .interact_text()?),
+    }
+}
+
+#[derive(Serialize)]
+struct ListItem<'a> {
+    name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<&'a str>,
+    tags: &'a [String],
+    method: &'a str,
+    url: &'a str,
+}
+
+// cargo run --bin xreq list --json
+async fn list(opts: ListOptions) -> Result<()> {
+    let config = RequestConfig::load(&opts.config, opts.format).await?;
+    let items = config
+        .select_items(&opts.filter.filter()?)
+        .map_err(|e| anyhow::anyhow!("xreq {} in config file {}", e, opts.config))?;
     let mut stdout = stdout().lock();
-    write!(stdout, "\n{}", after_highlight.unwrap())?;
+    if opts.json {
+        let items: Vec<_> = items
+            .iter()
+            .map(|(name, item)| ListItem {
+                name,
+                description: item.description(),
+                tags: item.tags(),
+                method: item.request().method().as_str(),
+                url: item.request().raw_url(),
+            })
+            .collect();
+        writeln!(stdout, "{}", serde_json::to_string_pretty(&items)?)?;
+        return Ok(());
+    }
+    for (name, item) in items {
+        let req = item.request();
+        writeln!(
+            stdout,
+            "{} {} {}{}",
+            style(name).bold(),
+            req.method(),
+            req.raw_url(),
+            tags_text(item.tags())
+        )?;
+        if let Some(description) = item.description() {
+            writeln!(stdout, "  {}", style(description).dim())?;
+        }
+    }
+    Ok(())
+}
+
+/// the tags of an item after its request in the list
+fn tags_text(tags: &[String]) -> String {
+    match tags.is_empty() {
+        true => String::new(),
+        false => format!(" {}", style(format!("[{}]", tags.join(", "))).cyan()),
+    }
+}
+
+// cargo run --bin xreq show user --env staging
+async fn show(opts: ShowOptions) -> Result<()> {
+    let config = RequestConfig::load(&opts.config, opts.format).await?;
+    let item = config.get_item(&opts.item).ok_or_else(|| {
+        anyhow::anyhow!(
+            "xreq item {} not found in config file {}",
+            opts.item,
+            opts.config
+        )
+    })?;
+    let item = match opts.env.as_deref() {
+        Some(name) => item.with_environment(config.environment(name)?)?,
+        None => item.clone(),
+    };
+    let output = serde_yaml::to_string(&IndexMap::from([(opts.item, item)]))?;
+    write!(stdout().lock(), "{}", highlight_text(&output, "yaml")?)?;
+    Ok(())
+}
+
+// cargo run --bin xreq schema -o xreq.schema.json
+fn schema(opts: SchemaOptions) -> Result<()> {
+    let output = serde_json::to_string_pretty(&RequestConfig::json_schema())?;
+    match opts.output {
+        Some(path) => fs::write(path, output + "\n")?,
+        None => writeln!(stdout().lock(), "{}", output)?,
+    }
+    Ok(())
+}
+
+// cargo run --bin xreq validate -c fixtures/xreq.yaml
+fn validate(opts: ValidateOptions) -> Result<()> {
+    let problems = RequestConfig::lint(&opts.config, opts.format);
+    let mut stdout = stdout().lock();
+    for problem in &problems {
+        writeln!(stdout, "{}", style(problem).red())?;
+    }
+    if !problems.is_empty() {
+        writeln!(
+            stdout,
+            "{} problem(s) found in {}",
+            problems.len(),
+            opts.config
+        )?;
+        std::process::exit(1);
+    }
+    writeln!(stdout, "{} is valid", opts.config)?;
     Ok(())
 }
 
 // cargo run --bin xreq run -i todo
+// cargo run --bin xreq run -i user --env staging
+// cargo run --bin xreq run -i todo -c fixtures/xreq.json
 async fn run(opts: RunOptions) -> Result<()> {
     let file = opts
         .config
         .unwrap_or_else(|| "fixtures/xreq.yaml".to_string());
-    let config = RequestConfig::load_yaml(&file).await?;
+    let config = RequestConfig::load(&file, opts.format).await?;
 
-    let item = config.get_item(&opts.item).ok_or_else(|| {
-        anyhow::anyhow!("xreq item {} not found in config file {}", opts.item, file)
-    })?;
+    let session = if opts.cookies || opts.cookie_jar.is_some() {
+        Session::with_cookies()
+    } else {
+        Session::new()
+    };
+    if let Some(path) = &opts.cookie_jar {
+        if Path::new(path).exists() {
+            session.load_cookies(COOKIE_SIDE, path)?;
+        }
+    }
+
+    let env = opts
+        .env
+        .as_deref()
+        .map(|name| config.environment(name))
+        .transpose()?;
+    let items = config
+        .select_items(&opts.filter.filter()?)
+        .map_err(|e| anyhow::anyhow!("xreq {} in config file {}", e, file))?;
+    if items.is_empty() {
+        return Err(anyhow::anyhow!(
+            "no items in config file {} match the filter",
+            file
+        ));
+    }
     let args = opts.args.into();
-    let url = item.url(&args)?;
-    let res = item.send(&args).await?.into_inner();
+    let mut failed = false;
+    for (_, item) in items {
+        let item = match env {
+            Some(env) => item.with_environment(env)?,
+            None => item.clone(),
+        };
+        failed |= !send(&item, &args, &session).await?;
+    }
+
+    if let Some(path) = &opts.cookie_jar {
+        session.save_cookies(COOKIE_SIDE, path)?;
+    }
+    if failed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// a request has no sides, so the `left:` and `right:` prefixes of xdiff are rejected
+fn parse_arg(s: &str) -> Result<KeyVal> {
+    let arg = parse_key_val(s)?;
+    match arg.side {
+        KeyValSide::Both => Ok(arg),
+        _ => Err(anyhow::anyhow!(
+            "{}: left: and right: only apply to the requests of xdiff",
+            s
+        )),
+    }
+}
+
+/// send the item and print the response, return false if any assertion failed
+async fn send(item: &RequestItem, args: &Args, session: &Session) -> Result<bool> {
+    let url = item.request().url(args)?;
+    let schema = item.schema().map(BodySchema::load).transpose()?;
+    let res = item
+        .request()
+        .send_with_cookies(args, session.cookie_jar(COOKIE_SIDE))
+        .await?;
+    let mut failures = res.check(item.assertions());
+    if let Some(schema) = &schema {
+        failures.extend(res.check_schema(schema));
+    }
+    let res = res.into_inner();
 
     let status = status_text(&res)?;
     let headers = headers_text(&res, &[])?;
@@ -110,5 +451,26 @@ async fn run(opts: RunOptions) -> Result<()> {
 
     let mut stdout = std::io::stdout().lock();
     write!(stdout, "{}", output)?;
-    Ok(())
+    for failure in &failures {
+        writeln!(stdout, "{}", style(failure).red())?;
+    }
+    Ok(failures.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn args_should_accept_removals_and_reject_sides() {
+        let opts = Options::try_parse_from(["xreq", "run", "-a", "-%x-token", "-a", "-page"]);
+        let Action::Run(opts) = opts.unwrap().action else {
+            panic!("expected run");
+        };
+        let keys: Vec<_> = opts.args.iter().map(|a| a.key.as_str()).collect();
+        assert_eq!(keys, vec!["x-token", "page"]);
+        assert!(opts.args.iter().all(|a| a.value.is_none()));
+
+        assert!(Options::try_parse_from(["xreq", "run", "-a", "left:page=1"]).is_err());
+    }
 }
@@ -1,43 +1,406 @@
-use anyhow::{anyhow, Result};
+use std::{
+    fs,
+    io::{stdout, Write},
+    path::Path,
+    str::FromStr,
+};
 
-#[derive(Debug, Clone)]
+use anyhow::{anyhow, Context, Result};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Method,
+};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{highlight_text, ConfigEditor, ConfigFormat, RequestContext};
+
+/// curl options which do not change the request
+const CURL_NOOP_OPTIONS: [&str; 9] = [
+    "-s",
+    "-S",
+    "-i",
+    "-v",
+    "-L",
+    "--silent",
+    "--show-error",
+    "--location",
+    "--compressed",
+];
+
+/// a no-op curl option, short flags may be bundled like `-sL`
+fn is_noop_option(word: &str) -> bool {
+    if CURL_NOOP_OPTIONS.contains(&word) {
+        return true;
+    }
+    match word.strip_prefix('-') {
+        Some(flags) if !flags.is_empty() && !flags.starts_with('-') => flags
+            .chars()
+            .all(|c| CURL_NOOP_OPTIONS.contains(&format!("-{}", c).as_str())),
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub enum KeyValType {
     /// if key has no any prefix, it is for query
+    #[default]
     Query,
-    /// if key starts with '#', it is for header
+    /// if key starts with '%', it is for header
     Header,
     /// if key starts with '@', it is for body
     Body,
 }
 
-impl Default for KeyValType {
-    fn default() -> Self {
-        Self::Query
-    }
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeyValSide {
+    /// if key has no side prefix, it is for both requests
+    #[default]
+    Both,
+    /// if key starts with `left:`, it is only for the baseline request
+    Left,
+    /// if key starts with `right:`, it is only for the requests diffed against the baseline
+    Right,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct KeyVal {
+    pub side: KeyValSide,
     pub key_type: KeyValType,
+    /// for body, a dot separated path like `user.address.city`. A trailing `[]`
+    /// appends the value to the array at the key instead of replacing it
     pub key: String,
-    pub value: String,
+    /// `None` removes the key from the request
+    pub value: Option<Value>,
 }
 
+/// parse an override in a httpie like syntax:
+/// - `key=value`: the value is a string
+/// - `key:=json`: the value is raw json, e.g. `count:=10` or `tags:='["a"]'`
+/// - `key=@file`: the value is read from a file, parsed as json if it ends with `.json`
+/// - `key:=@file`: the value is read from a file and parsed as json
+/// - `-key`: the key is removed from the configured request
+///
+/// A `left:` or `right:` prefix limits the override to one side of a diff.
 pub fn parse_key_val(s: &str) -> Result<KeyVal> {
-    let mut parts = s.splitn(2, '=');
+    let (side, s) = if let Some(s) = s.strip_prefix("left:") {
+        (KeyValSide::Left, s)
+    } else if let Some(s) = s.strip_prefix("right:") {
+        (KeyValSide::Right, s)
+    } else {
+        (KeyValSide::Both, s)
+    };
+
+    if let Some(key) = s.strip_prefix('-') {
+        if key.contains('=') {
+            return Err(anyhow!("invalid removal: {}, it takes no value", s));
+        }
+        let (key_type, key) = parse_key(key)?;
+        return Ok(KeyVal {
+            side,
+            key_type,
+            key,
+            value: None,
+        });
+    }
+
+    let pos = s.find('=').ok_or_else(|| anyhow!("invalid value"))?;
+    let (key, raw, value) = match s[..pos].strip_suffix(':') {
+        Some(key) => (key, true, &s[pos + 1..]),
+        None => (&s[..pos], false, &s[pos + 1..]),
+    };
+    let (key_type, key) = parse_key(key)?;
+
+    let value = match value.strip_prefix('@') {
+        Some(path) => {
+            let content =
+                fs::read_to_string(path).with_context(|| format!("failed to read {}", path))?;
+            let is_json = Path::new(path).extension().is_some_and(|ext| ext == "json");
+            if raw || is_json {
+                serde_json::from_str(&content)
+                    .with_context(|| format!("invalid json in {}", path))?
+            } else {
+                Value::String(content.trim_end_matches(['\r', '\n']).to_string())
+            }
+        }
+        None if raw => {
+            serde_json::from_str(value).with_context(|| format!("invalid json: {}", value))?
+        }
+        // `\@` escapes a literal `@` at the start of a string value
+        None => {
+            let unescaped = value.strip_prefix('\\').filter(|v| v.starts_with('@'));
+            Value::String(unescaped.unwrap_or(value).to_string())
+        }
+    };
+
+    Ok(KeyVal {
+        side,
+        key_type,
+        key,
+        value: Some(value),
+    })
+}
+
+/// parse a header in the curl syntax `name: value`
+pub fn parse_header(s: &str) -> Result<(HeaderName, HeaderValue)> {
+    let (name, value) = s
+        .split_once(':')
+        .ok_or_else(|| anyhow!("invalid header: {}, expect name: value", s))?;
+    let name = HeaderName::from_str(name.trim())
+        .with_context(|| format!("invalid header name: {}", name))?;
+    let value = HeaderValue::from_str(value.trim())
+        .with_context(|| format!("invalid header value: {}", value))?;
+    Ok((name, value))
+}
+
+/// parse a http method case insensitively
+pub fn parse_method(s: &str) -> Result<Method> {
+    Method::from_str(&s.to_uppercase()).map_err(|_| anyhow!("invalid method: {}", s))
+}
+
+/// parse a json body, `@file` reads it from a file
+pub fn parse_body(s: &str) -> Result<Value> {
+    let content = match s.strip_prefix('@') {
+        Some(path) => {
+            fs::read_to_string(path).with_context(|| format!("failed to read {}", path))?
+        }
+        None => s.to_string(),
+    };
+    serde_json::from_str(&content).with_context(|| format!("body must be json: {}", s))
+}
+
+/// parse a url or a curl command like `curl -X POST -H 'x-token: a' -d '{}' https://a.com`
+pub fn parse_request(s: &str) -> Result<RequestContext> {
+    let s = s.trim();
+    let Some(cmd) = s.strip_prefix("curl ") else {
+        return s.parse();
+    };
+    let words = shell_words::split(cmd).with_context(|| format!("invalid curl command: {}", s))?;
+    let mut words = words.into_iter();
+    let mut url = None;
+    let mut method = None;
+    let mut headers = HeaderMap::new();
+    let mut body = None;
+    while let Some(word) = words.next() {
+        // short options may be written with their value like `-XPOST`
+        let split = word.char_indices().nth(2).map(|(idx, _)| idx);
+        let (option, attached) = match split {
+            Some(idx) if word.starts_with('-') && !word.starts_with("--") => {
+                (word[..idx].to_string(), Some(word[idx..].to_string()))
+            }
+            _ => (word.clone(), None),
+        };
+        let mut value = || {
+            attached
+                .clone()
+                .or_else(|| words.next())
+                .ok_or_else(|| anyhow!("curl option {} needs a value", option))
+        };
+        match option.as_str() {
+            "-X" | "--request" => method = Some(parse_method(&value()?)?),
+            "-H" | "--header" => {
+                let (name, value) = parse_header(&value()?)?;
+                headers.insert(name, value);
+            }
+            "-d" | "--data" | "--data-raw" | "--data-binary" | "--json" => {
+                body = Some(parse_body(&value()?)?)
+            }
+            "--url" => url = Some(value()?),
+            _ if is_noop_option(&word) => {}
+            _ if word.starts_with('-') => {
+                return Err(anyhow!("unsupported curl option: {}", word));
+            }
+            _ if url.is_some() => return Err(anyhow!("curl command has more than one url")),
+            _ => url = Some(word),
+        }
+    }
+
+    let url = url.ok_or_else(|| anyhow!("curl command has no url: {}", s))?;
+    let req: RequestContext = url.parse()?;
+    let method = method.unwrap_or(match body {
+        Some(_) => Method::POST,
+        None => Method::GET,
+    });
+    let req = req.with_method(method).with_headers(headers);
+    Ok(match body {
+        Some(body) => req.with_body(body),
+        None => req,
+    })
+}
+
+/// add a generated item to the config file `output`, the other items keep their layout and
+/// comments. Without `output` the item is printed as a config of its own
+pub fn write_item<T: Serialize>(
+    name: &str,
+    item: &T,
+    output: Option<&str>,
+    format: Option<ConfigFormat>,
+    force: bool,
+) -> Result<()> {
+    let Some(path) = output else {
+        let format = format.unwrap_or_default();
+        let mut editor = ConfigEditor::new("", format)?;
+        editor.insert(name, item)?;
+        let output = highlight_text(editor.as_str(), format.extension())?;
+        write!(stdout().lock(), "\n{}", output)?;
+        return Ok(());
+    };
+    let mut editor = ConfigEditor::open(path, format)?;
+    if editor.contains(name) && !force {
+        return Err(anyhow!(
+            "item {} already exists in {}, use --force to overwrite it",
+            name,
+            path
+        ));
+    }
+    editor.insert(name, item)?;
+    editor.save(path)?;
+    println!("item {} added to {}", name, path);
+    Ok(())
+}
 
-    let key = parts.next().ok_or_else(|| anyhow!("invalid key"))?;
-    let value = parts.next().ok_or_else(|| anyhow!("invalid value"))?;
-    let (key_type, key) = match key.chars().next() {
+fn parse_key(key: &str) -> Result<(KeyValType, String)> {
+    let (key_type, name) = match key.chars().next() {
         Some('%') => (KeyValType::Header, key[1..].to_string()),
         Some('@') => (KeyValType::Body, key[1..].to_string()),
         Some(v) if v.is_ascii_alphabetic() => (KeyValType::Query, key.to_string()),
         _ => Err(anyhow!("invalid key: {}", key))?,
     };
+    if name.is_empty() || name == "[]" {
+        return Err(anyhow!("invalid key: {}", key));
+    }
+    Ok((key_type, name))
+}
 
-    Ok(KeyVal {
-        key_type,
-        key: key.to_string(),
-        value: value.to_string(),
-    })
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn kv(key_type: KeyValType, key: &str, value: Value) -> KeyVal {
+        KeyVal {
+            side: KeyValSide::Both,
+            key_type,
+            key: key.to_string(),
+            value: Some(value),
+        }
+    }
+
+    #[test]
+    fn parse_key_val_should_work() {
+        assert_eq!(
+            parse_key_val("name=alice").unwrap(),
+            kv(KeyValType::Query, "name", json!("alice"))
+        );
+        assert_eq!(
+            parse_key_val("%x-token=a=b").unwrap(),
+            kv(KeyValType::Header, "x-token", json!("a=b"))
+        );
+        assert_eq!(
+            parse_key_val("@user.age:=30").unwrap(),
+            kv(KeyValType::Body, "user.age", json!(30))
+        );
+        assert_eq!(
+            parse_key_val(r#"@tags[]:={"a":1}"#).unwrap(),
+            kv(KeyValType::Body, "tags[]", json!({"a": 1}))
+        );
+        assert_eq!(
+            parse_key_val(r"@email=\@alice").unwrap(),
+            kv(KeyValType::Body, "email", json!("@alice"))
+        );
+        assert_eq!(
+            parse_key_val(r"q=\d+").unwrap(),
+            kv(KeyValType::Query, "q", json!(r"\d+"))
+        );
+        assert!(parse_key_val("@age:=thirty").is_err());
+        assert!(parse_key_val("=1").is_err());
+        assert!(parse_key_val("name").is_err());
+    }
+
+    #[test]
+    fn parse_key_val_should_read_files() {
+        let kv = parse_key_val("@todo=@fixtures/todo.schema.json").unwrap();
+        assert_eq!(kv.value.unwrap()["type"], json!("object"));
+        let kv = parse_key_val("@diff=@fixtures/diff.txt").unwrap();
+        assert!(kv.value.unwrap().is_string());
+        assert!(parse_key_val("@diff:=@fixtures/diff.txt").is_err());
+        assert!(parse_key_val("@x=@fixtures/missing.json").is_err());
+    }
+
+    #[test]
+    fn parse_key_val_should_support_sides_and_removal() {
+        let kv = parse_key_val("right:%x-feature=on").unwrap();
+        assert_eq!(kv.side, KeyValSide::Right);
+        assert_eq!(kv.key_type, KeyValType::Header);
+        assert_eq!(kv.value, Some(json!("on")));
+
+        let kv = parse_key_val("left:-@user.name").unwrap();
+        assert_eq!(kv.side, KeyValSide::Left);
+        assert_eq!(kv.key_type, KeyValType::Body);
+        assert_eq!(kv.key, "user.name");
+        assert_eq!(kv.value, None);
+
+        let kv = parse_key_val("-page").unwrap();
+        assert_eq!(
+            (kv.side, kv.key_type),
+            (KeyValSide::Both, KeyValType::Query)
+        );
+        assert!(parse_key_val("-page=1").is_err());
+    }
+
+    #[test]
+    fn parse_header_should_work() {
+        let (name, value) = parse_header("X-Token: a:b").unwrap();
+        assert_eq!(name, "x-token");
+        assert_eq!(value, "a:b");
+        assert!(parse_header("x-token").is_err());
+        assert!(parse_header("x token: a").is_err());
+        assert_eq!(parse_method("post").unwrap(), Method::POST);
+    }
+
+    #[test]
+    fn parse_request_should_support_curl_commands() {
+        let req = parse_request("https://a.com/x?q=1").unwrap();
+        assert_eq!(req.method(), Method::GET);
+        assert_eq!(req.raw_url(), "https://a.com/x");
+
+        let req = parse_request(
+            "curl -sS -XPUT 'https://a.com/users/1?v=2' \\\n  -H 'x-token: a b' --data '{\"name\": \"alice\"}'",
+        )
+        .unwrap();
+        assert_eq!(req.method(), Method::PUT);
+        assert_eq!(req.raw_url(), "https://a.com/users/1");
+        let (headers, query, body) = req.generate(&Default::default()).unwrap();
+        assert_eq!(headers.get("x-token").unwrap(), "a b");
+        assert_eq!(query, json!({"v": 2}));
+        assert_eq!(body, r#"{"name":"alice"}"#);
+
+        let req = parse_request("curl --json '{}' https://a.com").unwrap();
+        assert_eq!(req.method(), Method::POST);
+        assert!(parse_request("curl -sLi https://a.com").is_ok());
+        assert!(parse_request("curl -k https://a.com").is_err());
+        assert!(parse_request("curl -sk https://a.com").is_err());
+        assert!(parse_request("curl -é https://a.com").is_err());
+        assert!(parse_request("curl -Hé https://a.com").is_err());
+        assert!(parse_request("curl -sé https://a.com").is_err());
+        assert!(parse_request("curl -H 'x: 1'").is_err());
+        assert!(parse_request("curl -d 'a=1' https://a.com").is_err());
+    }
+
+    #[test]
+    fn write_item_should_add_to_the_config_file() {
+        let path = std::env::temp_dir().join(format!("xdiff-cli-{}.toml", std::process::id()));
+        let content = "# the first item\n[b]\nurl = \"https://b.com/\"\n";
+        fs::write(&path, content).unwrap();
+        let path_str = path.to_str().unwrap();
+        let req: RequestContext = "https://a.com/".parse().unwrap();
+        write_item("a", &req, Some(path_str), None, false).unwrap();
+        assert!(write_item("b", &req, Some(path_str), None, false).is_err());
+
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.starts_with(content), "{}", text);
+        assert!(text.find("[b]").unwrap() < text.find("[a]").unwrap());
+        fs::remove_file(path).unwrap();
+    }
 }
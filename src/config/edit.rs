@@ -0,0 +1,495 @@
+use std::{fs, io, path::Path};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+use serde_yaml::{Mapping, Value};
+use toml_edit::{DocumentMut, Item, Key, Table};
+use yaml_rust2::{parser::Parser, Event};
+
+use super::ConfigFormat;
+
+/// top level keys of a config which are not items
+const RESERVED: [&str; 4] = ["include", "environments", "fragments", "defaults"];
+
+/// edits the items of a config text. Yaml and toml texts are edited in place so the layout
+/// and comments of the other items are kept, json configs are written again in order
+#[derive(Debug, Clone)]
+pub struct ConfigEditor {
+    text: String,
+    format: ConfigFormat,
+}
+
+/// a top level entry of a yaml config by line
+struct Entry {
+    name: String,
+    /// first line, including the comments right above the key
+    start: usize,
+    /// line of the key
+    key: usize,
+    /// end of the value, the comments and blank lines after it are not part of it
+    end: usize,
+}
+
+impl ConfigEditor {
+    pub fn new(text: impl Into<String>, format: ConfigFormat) -> Result<Self> {
+        let editor = Self {
+            text: text.into(),
+            format,
+        };
+        editor.mapping()?;
+        Ok(editor)
+    }
+
+    /// open a config file, a missing file is an empty config. The format is detected from the
+    /// file extension by default
+    pub fn open(path: impl AsRef<Path>, format: Option<ConfigFormat>) -> Result<Self> {
+        let path = path.as_ref();
+        let format = format.unwrap_or_else(|| ConfigFormat::from_path(path));
+        let text = match fs::read_to_string(path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            text => text.with_context(|| format!("failed to read {}", path.display()))?,
+        };
+        Self::new(text, format).with_context(|| format!("failed to parse {}", path.display()))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        fs::write(path, &self.text).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// names of the items in the order they are written
+    pub fn names(&self) -> Vec<String> {
+        self.mapping()
+            .unwrap_or_default()
+            .keys()
+            .filter_map(|k| k.as_str())
+            .filter(|k| !RESERVED.contains(k))
+            .map(String::from)
+            .collect()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.names().iter().any(|n| n == name)
+    }
+
+    /// add an item at the end, an item of the same name is replaced in place
+    pub fn insert<T: Serialize>(&mut self, name: &str, item: &T) -> Result<()> {
+        check_name(name)?;
+        let value = serde_yaml::to_value(item)?;
+        let mut expected = self.mapping()?;
+        expected.insert(name.into(), value.clone());
+        if self.format == ConfigFormat::Toml {
+            let edited = self.edit_toml(|table| insert_toml(table, name, &value));
+            return self.commit(expected, edited);
+        }
+
+        let edited = self.entries().map(|entries| {
+            let mut entry = serde_yaml::to_string(&Mapping::from_iter([(name.into(), value)]))
+                .unwrap_or_default();
+            let mut lines = self.lines();
+            match entries.iter().find(|e| e.name == name) {
+                Some(e) => {
+                    lines.splice(e.key..e.end, [entry.as_str()]);
+                    lines.concat()
+                }
+                None => {
+                    let mut text = lines.concat();
+                    if !text.is_empty() && !text.ends_with('\n') {
+                        text.push('\n');
+                    }
+                    // keep the items apart the way the existing ones are
+                    let spaced = entries
+                        .iter()
+                        .skip(1)
+                        .any(|e| e.start > 0 && lines[e.start - 1].trim().is_empty());
+                    if spaced {
+                        entry.insert(0, '\n');
+                    }
+                    text + &entry
+                }
+            }
+        });
+        self.commit(expected, edited)
+    }
+
+    /// remove an item with the comments right above it, returns false if it does not exist
+    pub fn remove(&mut self, name: &str) -> Result<bool> {
+        if !self.contains(name) {
+            return Ok(false);
+        }
+        let mut expected = self.mapping()?;
+        expected.shift_remove(name);
+        if self.format == ConfigFormat::Toml {
+            let edited = self.edit_toml(|table| table.remove(name).map(|_| ()));
+            self.commit(expected, edited)?;
+            return Ok(true);
+        }
+
+        let edited = self.entries().map(|entries| {
+            let mut lines = self.lines();
+            if let Some(e) = entries.iter().find(|e| e.name == name) {
+                // drop the blank lines after the item as well so the separation stays the same
+                let next = entries
+                    .iter()
+                    .find(|n| n.start > e.key)
+                    .map_or(lines.len(), |n| n.start);
+                let mut end = e.end;
+                while end < next && lines[end].trim().is_empty() {
+                    end += 1;
+                }
+                lines.drain(e.start..end);
+            }
+            lines.concat()
+        });
+        self.commit(expected, edited)?;
+        Ok(true)
+    }
+
+    /// rename an item in place, its value and comments are kept
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<()> {
+        check_name(to)?;
+        if !self.contains(from) {
+            return Err(anyhow!("item {} does not exist", from));
+        }
+        if self.contains(to) {
+            return Err(anyhow!("item {} already exists", to));
+        }
+        let expected: Mapping = self
+            .mapping()?
+            .into_iter()
+            .map(|(k, v)| match k.as_str() == Some(from) {
+                true => (to.into(), v),
+                false => (k, v),
+            })
+            .collect();
+        if self.format == ConfigFormat::Toml {
+            let edited = self.edit_toml(|table| rename_toml(table, from, to));
+            return self.commit(expected, edited);
+        }
+
+        let edited = self.entries().and_then(|entries| {
+            let e = entries.iter().find(|e| e.name == from)?;
+            let mut lines: Vec<String> = self.lines().into_iter().map(String::from).collect();
+            let len = key_len(&lines[e.key], from)?;
+            let key = serde_yaml::to_string(to).ok()?;
+            lines[e.key].replace_range(..len, key.trim_end());
+            Some(lines.concat())
+        });
+        self.commit(expected, edited)
+    }
+
+    fn mapping(&self) -> Result<Mapping> {
+        if self.text.trim().is_empty() {
+            return Ok(Mapping::new());
+        }
+        match self.format.parse(&self.text)? {
+            Value::Null => Ok(Mapping::new()),
+            Value::Mapping(map) => Ok(map),
+            _ => Err(anyhow!("config must be a mapping of items")),
+        }
+    }
+
+    /// take the edited text if it has the expected items, otherwise write all items again
+    fn commit(&mut self, expected: Mapping, edited: Option<String>) -> Result<()> {
+        let expected = Value::Mapping(expected);
+        self.text = match edited {
+            Some(text) if self.format.parse(&text).ok().as_ref() == Some(&expected) => text,
+            _ => self.format.to_string(&expected)?,
+        };
+        Ok(())
+    }
+
+    /// edit the top level table of a toml config, none if it can't be parsed or edited
+    fn edit_toml(&self, edit: impl FnOnce(&mut Table) -> Option<()>) -> Option<String> {
+        let mut doc: DocumentMut = self.text.parse().ok()?;
+        edit(doc.as_table_mut())?;
+        Some(doc.to_string())
+    }
+
+    fn lines(&self) -> Vec<&str> {
+        self.text.split_inclusive('\n').collect()
+    }
+
+    /// the top level entries of a yaml config, none if it is not a block mapping
+    fn entries(&self) -> Option<Vec<Entry>> {
+        if self.format != ConfigFormat::Yaml {
+            return None;
+        }
+        let keys = top_level_keys(&self.text)?;
+        let lines = self.lines();
+        let mut entries: Vec<Entry> = Vec::with_capacity(keys.len());
+        for (idx, (name, key)) in keys.iter().enumerate() {
+            let mut start = *key;
+            while start > 0 && lines[start - 1].starts_with('#') {
+                start -= 1;
+            }
+            if let Some(prev) = entries.last() {
+                start = start.max(prev.key + 1);
+            }
+            let next = keys.get(idx + 1).map_or(lines.len(), |(_, line)| *line);
+            let end = (*key + 1..next)
+                .rev()
+                .find(|i| is_value_line(lines[*i]))
+                .map_or(*key + 1, |i| i + 1);
+            entries.push(Entry {
+                name: name.clone(),
+                start,
+                key: *key,
+                end,
+            });
+        }
+        Some(entries)
+    }
+}
+
+fn check_name(name: &str) -> Result<()> {
+    match RESERVED.contains(&name) {
+        true => Err(anyhow!("{} is reserved and can not be an item name", name)),
+        false => Ok(()),
+    }
+}
+
+/// add or replace an item of a toml config, a replaced item keeps its place and comments
+fn insert_toml(table: &mut Table, name: &str, value: &Value) -> Option<()> {
+    let text = toml::to_string(&Mapping::from_iter([(name.into(), value.clone())])).ok()?;
+    let mut item = text.parse::<DocumentMut>().ok()?.remove(name)?;
+    let position = match (table.get(name), &mut item) {
+        (Some(Item::Table(old)), Item::Table(new)) => {
+            *new.decor_mut() = old.decor().clone();
+            old.position()
+        }
+        (_, new) => {
+            // a new table gets the default blank line before its header
+            if let Item::Table(new) = new {
+                new.decor_mut().clear();
+            }
+            None
+        }
+    };
+    let position = position.unwrap_or_else(|| last_position(table) + 1);
+    set_position(&mut item, position);
+    table.insert(name, item);
+    Some(())
+}
+
+/// rename an item of a toml config, the items are added again in the same order
+fn rename_toml(table: &mut Table, from: &str, to: &str) -> Option<()> {
+    let keys: Vec<String> = table.iter().map(|(k, _)| k.to_string()).collect();
+    let entries: Vec<(Key, Item)> = keys.iter().filter_map(|k| table.remove_entry(k)).collect();
+    for (key, item) in entries {
+        match key.get() == from {
+            true => table.insert_formatted(&Key::new(to), item),
+            false => table.insert_formatted(&key, item),
+        };
+    }
+    Some(())
+}
+
+/// the last position of the tables, tables are written in the order of their positions
+fn last_position(table: &Table) -> usize {
+    let nested = table.iter().map(|(_, item)| match item {
+        Item::Table(t) => last_position(t),
+        Item::ArrayOfTables(a) => a.iter().map(last_position).max().unwrap_or_default(),
+        _ => 0,
+    });
+    nested.chain(table.position()).max().unwrap_or_default()
+}
+
+/// write the tables of an item at `position`, the nested ones follow it in order
+fn set_position(item: &mut Item, position: usize) {
+    let set = |table: &mut Table| {
+        table.set_position(position);
+        table
+            .iter_mut()
+            .for_each(|(_, item)| set_position(item, position));
+    };
+    match item {
+        Item::Table(table) => set(table),
+        Item::ArrayOfTables(array) => array.iter_mut().for_each(set),
+        _ => {}
+    }
+}
+
+/// a line of a value under a top level key: indented or a sequence entry
+fn is_value_line(line: &str) -> bool {
+    !line.trim().is_empty() && (line.starts_with([' ', '\t']) || line.starts_with('-'))
+}
+
+/// length of the key at the start of a line, quoted or not
+fn key_len(line: &str, name: &str) -> Option<usize> {
+    let len = match line.chars().next()? {
+        quote @ ('"' | '\'') => line[1..].find(quote)? + 2,
+        _ => name.len(),
+    };
+    line[len..].trim_start().starts_with(':').then_some(len)
+}
+
+/// the top level keys with their lines, none unless they are all plain keys of a block mapping
+fn top_level_keys(text: &str) -> Option<Vec<(String, usize)>> {
+    let mut parser = Parser::new_from_str(text);
+    let mut keys = vec![];
+    let mut depth = 0;
+    let mut expect_key = true;
+    loop {
+        let (event, marker) = parser.next_token().ok()?;
+        match event {
+            Event::StreamEnd => break,
+            Event::MappingStart(..) | Event::SequenceStart(..) => {
+                if depth == 0 && !matches!(event, Event::MappingStart(..)) {
+                    return None;
+                }
+                if depth == 1 && expect_key {
+                    return None;
+                }
+                depth += 1;
+            }
+            Event::MappingEnd | Event::SequenceEnd => {
+                depth -= 1;
+                if depth == 1 {
+                    expect_key = true;
+                }
+            }
+            Event::Scalar(..) | Event::Alias(..) if depth == 1 => {
+                if expect_key {
+                    let Event::Scalar(name, ..) = event else {
+                        return None;
+                    };
+                    if marker.col() != 0 {
+                        return None;
+                    }
+                    keys.push((name, marker.line() - 1));
+                }
+                expect_key = !expect_key;
+            }
+            _ => {}
+        }
+    }
+    Some(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Load, RequestConfig};
+
+    const CONFIG: &str = "---
+# shared settings
+environments:
+  staging:
+    base_url: https://staging.com
+
+# the first item
+b:
+  url: https://b.com/ # inline
+  method: GET
+
+a:
+  url: https://a.com/
+# trailing notes
+";
+
+    fn editor() -> ConfigEditor {
+        ConfigEditor::new(CONFIG, ConfigFormat::Yaml).unwrap()
+    }
+
+    fn item(url: &str) -> Value {
+        serde_yaml::from_str(&format!("url: {}", url)).unwrap()
+    }
+
+    #[test]
+    fn insert_should_keep_layout_and_comments() {
+        let mut editor = editor();
+        assert_eq!(editor.names(), vec!["b", "a"]);
+        editor.insert("c", &item("https://c.com/")).unwrap();
+        assert_eq!(
+            editor.as_str(),
+            format!("{}\nc:\n  url: https://c.com/\n", CONFIG)
+        );
+
+        let mut editor = self::editor();
+        editor.insert("b", &item("https://d.com/")).unwrap();
+        assert_eq!(
+            editor.as_str(),
+            CONFIG.replace(
+                "b:\n  url: https://b.com/ # inline\n  method: GET\n",
+                "b:\n  url: https://d.com/\n"
+            )
+        );
+        let config = RequestConfig::from_yaml(editor.as_str()).unwrap();
+        let names: Vec<_> = config.items().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["b", "a"]);
+
+        assert!(editor.insert("defaults", &item("https://d.com/")).is_err());
+    }
+
+    #[test]
+    fn remove_and_rename_should_keep_comments() {
+        let mut editor = editor();
+        assert!(editor.remove("b").unwrap());
+        assert!(!editor.remove("b").unwrap());
+        assert_eq!(
+            editor.as_str(),
+            CONFIG.replace(
+                "# the first item\nb:\n  url: https://b.com/ # inline\n  method: GET\n\n",
+                ""
+            )
+        );
+
+        let mut editor = self::editor();
+        editor.rename("b", "b 2").unwrap();
+        assert_eq!(editor.as_str(), CONFIG.replace("\nb:", "\nb 2:"));
+        editor.rename("b 2", "x: y").unwrap();
+        assert_eq!(editor.as_str(), CONFIG.replace("\nb:", "\n'x: y':"));
+        assert!(editor.rename("a", "x: y").is_err());
+        assert!(editor.rename("c", "d").is_err());
+    }
+
+    #[test]
+    fn editor_should_write_other_formats_in_order() {
+        let mut editor = ConfigEditor::new("", ConfigFormat::Json).unwrap();
+        editor.insert("b", &item("https://b.com/")).unwrap();
+        editor.insert("a", &item("https://a.com/")).unwrap();
+        editor.rename("b", "c").unwrap();
+        assert_eq!(editor.names(), vec!["c", "a"]);
+        let config = RequestConfig::from_json(editor.as_str()).unwrap();
+        assert!(config.get_item("c").is_some());
+
+        let mut editor =
+            ConfigEditor::new("{b: {url: 'https://b.com/'}}", ConfigFormat::Yaml).unwrap();
+        editor.insert("a", &item("https://a.com/")).unwrap();
+        assert_eq!(editor.names(), vec!["b", "a"]);
+        assert!(ConfigEditor::new("- a", ConfigFormat::Yaml).is_err());
+    }
+
+    #[test]
+    fn editor_should_keep_toml_layout_and_comments() {
+        let config = "# shared settings\n[environments.staging]\nbase_url = \"https://s.com\"\n\n\
+                      # the first item\n[b]\nurl = \"https://b.com/\" # inline\n\n\
+                      [b.params]\npage = 1\n\n[a]\nurl = \"https://a.com/\"\n";
+        let mut editor = ConfigEditor::new(config, ConfigFormat::Toml).unwrap();
+        editor.insert("c", &item("https://c.com/")).unwrap();
+        assert_eq!(
+            editor.as_str(),
+            format!("{}\n[c]\nurl = \"https://c.com/\"\n", config)
+        );
+
+        editor.insert("b", &item("https://d.com/")).unwrap();
+        assert!(editor
+            .as_str()
+            .contains("# the first item\n[b]\nurl = \"https://d.com/\"\n"));
+        assert!(!editor.as_str().contains("[b.params]"));
+        assert_eq!(editor.names(), vec!["b", "a", "c"]);
+
+        editor.rename("b", "b 2").unwrap();
+        assert!(editor.as_str().contains("# the first item\n[\"b 2\"]\n"));
+        assert!(editor.remove("a").unwrap());
+        assert_eq!(editor.names(), vec!["b 2", "c"]);
+        assert!(editor.as_str().starts_with("# shared settings\n"));
+        let config = RequestConfig::from_toml(editor.as_str()).unwrap();
+        assert!(config.get_item("b 2").is_some());
+    }
+}
@@ -0,0 +1,141 @@
+use anyhow::{anyhow, Context, Result};
+use glob::Pattern;
+use indexmap::IndexMap;
+
+/// an item of a config which can be selected by its tags
+pub(crate) trait Tagged {
+    fn tags(&self) -> &[String];
+}
+
+/// selects the items of a config by name and tags
+#[derive(Debug, Clone, Default)]
+pub struct ItemFilter {
+    names: Vec<NameFilter>,
+    tags: Vec<String>,
+    exclude_tags: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+enum NameFilter {
+    Exact(String),
+    Glob(Pattern),
+}
+
+impl ItemFilter {
+    /// names are exact item names or globs like `user_*`. Without names all items are
+    /// selected, without tags the tags are not checked
+    pub fn new(names: &[String], tags: &[String], exclude_tags: &[String]) -> Result<Self> {
+        let names = names
+            .iter()
+            .map(|name| match name.contains(['*', '?', '[']) {
+                true => Pattern::new(name)
+                    .map(NameFilter::Glob)
+                    .with_context(|| format!("invalid item pattern: {}", name)),
+                false => Ok(NameFilter::Exact(name.clone())),
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            names,
+            tags: tags.to_vec(),
+            exclude_tags: exclude_tags.to_vec(),
+        })
+    }
+
+    /// the items matching the filter, exact names in the given order and the others in the
+    /// order of the config. An exact name which is not in the config is an error
+    pub(crate) fn select<'a, T: Tagged>(
+        &self,
+        items: &'a IndexMap<String, T>,
+    ) -> Result<Vec<(&'a str, &'a T)>> {
+        let mut selected: Vec<(&str, &T)> = vec![];
+        let mut add = |name: &'a String, item: &'a T| {
+            if !selected.iter().any(|(n, _)| *n == name) {
+                selected.push((name.as_str(), item));
+            }
+        };
+        if self.names.is_empty() {
+            items.iter().for_each(|(name, item)| add(name, item));
+        }
+        for filter in &self.names {
+            match filter {
+                NameFilter::Exact(name) => {
+                    let (name, item) = items
+                        .get_key_value(name)
+                        .ok_or_else(|| anyhow!("item {} not found", name))?;
+                    add(name, item);
+                }
+                NameFilter::Glob(pattern) => items
+                    .iter()
+                    .filter(|(name, _)| pattern.matches(name))
+                    .for_each(|(name, item)| add(name, item)),
+            }
+        }
+        selected.retain(|(_, item)| self.matches_tags(item.tags()));
+        Ok(selected)
+    }
+
+    fn matches_tags(&self, tags: &[String]) -> bool {
+        (self.tags.is_empty() || self.tags.iter().any(|t| tags.contains(t)))
+            && !self.exclude_tags.iter().any(|t| tags.contains(t))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Item(Vec<String>);
+
+    impl Tagged for Item {
+        fn tags(&self) -> &[String] {
+            &self.0
+        }
+    }
+
+    fn items() -> IndexMap<String, Item> {
+        [
+            ("user_get", vec!["smoke"]),
+            ("user_list", vec!["full", "slow"]),
+            ("todo", vec!["smoke", "full"]),
+            ("nightly", vec![]),
+        ]
+        .into_iter()
+        .map(|(name, tags)| {
+            let tags = tags.into_iter().map(String::from).collect();
+            (name.to_string(), Item(tags))
+        })
+        .collect()
+    }
+
+    fn select(names: &[&str], tags: &[&str], exclude_tags: &[&str]) -> Result<Vec<String>> {
+        let strings = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let filter = ItemFilter::new(&strings(names), &strings(tags), &strings(exclude_tags))?;
+        let items = items();
+        let selected = filter.select(&items)?;
+        Ok(selected.iter().map(|(name, _)| name.to_string()).collect())
+    }
+
+    #[test]
+    fn select_should_filter_by_names_and_tags() {
+        assert_eq!(
+            select(&[], &[], &[]).unwrap(),
+            vec!["user_get", "user_list", "todo", "nightly"]
+        );
+        assert_eq!(
+            select(&[], &["smoke"], &[]).unwrap(),
+            vec!["user_get", "todo"]
+        );
+        assert_eq!(select(&[], &["full"], &["slow"]).unwrap(), vec!["todo"]);
+        assert_eq!(
+            select(&["todo", "user_*"], &[], &[]).unwrap(),
+            vec!["todo", "user_get", "user_list"]
+        );
+        assert_eq!(
+            select(&["user_*"], &[], &["slow"]).unwrap(),
+            vec!["user_get"]
+        );
+        assert!(select(&["x*"], &[], &[]).unwrap().is_empty());
+        assert!(select(&["unknown"], &[], &[]).is_err());
+        assert!(select(&["[a"], &[], &[]).is_err());
+    }
+}
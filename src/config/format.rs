@@ -0,0 +1,132 @@
+use std::{fmt, path::Path, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use serde_yaml::Value;
+
+/// file format of a config, detected from the file extension by default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConfigFormat {
+    #[default]
+    Yaml,
+    Json,
+    Toml,
+}
+
+impl ConfigFormat {
+    /// the format of a file by its extension, unknown extensions are yaml
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Self::Json,
+            Some("toml") => Self::Toml,
+            _ => Self::Yaml,
+        }
+    }
+
+    /// file extension of the format, also used to highlight the text
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Yaml => "yaml",
+            Self::Json => "json",
+            Self::Toml => "toml",
+        }
+    }
+
+    /// parse a config to the yaml value the loader works on
+    pub(crate) fn parse(&self, content: &str) -> Result<Value> {
+        Ok(match self {
+            Self::Yaml => serde_yaml::from_str(content)?,
+            Self::Json => serde_json::from_str(content)?,
+            Self::Toml => serde_yaml::to_value(toml::from_str::<toml::Value>(content)?)?,
+        })
+    }
+
+    pub fn to_string<T: Serialize>(&self, config: &T) -> Result<String> {
+        Ok(match self {
+            Self::Yaml => serde_yaml::to_string(config)?,
+            Self::Json => serde_json::to_string_pretty(config)? + "\n",
+            Self::Toml => toml::to_string_pretty(config)?,
+        })
+    }
+}
+
+impl FromStr for ConfigFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "yaml" | "yml" => Ok(Self::Yaml),
+            "json" => Ok(Self::Json),
+            "toml" => Ok(Self::Toml),
+            _ => Err(anyhow!(
+                "unknown config format {}, expect yaml, json or toml",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ConfigFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DiffConfig, Load, RequestConfig};
+
+    fn value<T: Serialize>(config: &T) -> Value {
+        serde_yaml::to_value(config).unwrap()
+    }
+
+    #[test]
+    fn from_path_should_work() {
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("a.json")),
+            ConfigFormat::Json
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("a.toml")),
+            ConfigFormat::Toml
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("a.yml")),
+            ConfigFormat::Yaml
+        );
+        assert_eq!("TOML".parse::<ConfigFormat>().unwrap(), ConfigFormat::Toml);
+        assert!("ini".parse::<ConfigFormat>().is_err());
+    }
+
+    #[tokio::test]
+    async fn configs_should_round_trip() {
+        let config = DiffConfig::load_yaml("fixtures/test.yaml").await.unwrap();
+        let json = DiffConfig::from_json(&ConfigFormat::Json.to_string(&config).unwrap()).unwrap();
+        let toml = DiffConfig::from_toml(&ConfigFormat::Toml.to_string(&config).unwrap()).unwrap();
+        assert_eq!(value(&json), value(&config));
+        assert_eq!(value(&toml), value(&config));
+
+        let config = RequestConfig::load_yaml("fixtures/xreq.yaml")
+            .await
+            .unwrap();
+        let toml =
+            RequestConfig::from_toml(&ConfigFormat::Toml.to_string(&config).unwrap()).unwrap();
+        assert_eq!(value(&toml), value(&config));
+    }
+
+    #[tokio::test]
+    async fn load_should_detect_format() {
+        let toml = DiffConfig::load("fixtures/test.toml", None).await.unwrap();
+        assert!(toml.get_item("todo").is_some());
+        let json = RequestConfig::load("fixtures/xreq.json", None)
+            .await
+            .unwrap();
+        assert!(json.get_item("todo").is_some());
+        assert!(
+            DiffConfig::load("fixtures/test.toml", Some(ConfigFormat::Json))
+                .await
+                .is_err()
+        );
+    }
+}
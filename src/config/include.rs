@@ -0,0 +1,471 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde_yaml::{Mapping, Value};
+
+use super::{merge::merge_patch, ConfigFormat, ItemError};
+
+/// top level sections whose entries are merged across files instead of being items
+const SECTIONS: [&str; 2] = ["environments", "fragments"];
+
+/// fields of an item holding the path of a json schema file
+const SCHEMA_FIELDS: [&[&str]; 2] = [&["schema"], &["res", "schema"]];
+
+/// a config merged from a file and the files it includes
+#[derive(Debug, Default)]
+pub(crate) struct Document {
+    pub(crate) value: Value,
+    /// the text of the config if it was not rewritten, to deserialize it with error locations
+    pub(crate) source: Option<String>,
+    /// file each item was defined in
+    origins: HashMap<String, PathBuf>,
+}
+
+impl Document {
+    /// load a config file, the includes are relative to the file. The format of
+    /// the included files is detected from their extension
+    pub(crate) fn load_file(path: &Path, format: Option<ConfigFormat>) -> Result<Self> {
+        let mut stack = vec![];
+        let mut doc = load(path, format, &mut stack)?;
+        doc.resolve_extends()?;
+        doc.resolve_schemas();
+        Ok(doc)
+    }
+
+    /// load a config from text, the includes are relative to the current directory
+    pub(crate) fn load_str(content: &str, format: ConfigFormat) -> Result<Self> {
+        let mut stack = vec![];
+        let mut doc = parse(content, format, None, Path::new("."), &mut stack)?;
+        doc.resolve_extends()?;
+        Ok(doc)
+    }
+
+    /// the file an item was defined in
+    pub(crate) fn origin(&self, name: &str) -> Option<&Path> {
+        self.origins.get(name).map(|p| p.as_path())
+    }
+
+    /// add the file of the failed item to the error
+    pub(crate) fn attribute(&self, err: anyhow::Error) -> anyhow::Error {
+        let file = err
+            .downcast_ref::<ItemError>()
+            .and_then(|item| self.origins.get(&item.0));
+        match file {
+            Some(file) => err.context(format!("in {}", file.display())),
+            None => err,
+        }
+    }
+
+    fn merge(&mut self, other: Document, file: Option<&Path>) -> Result<()> {
+        if !self.value.is_mapping() {
+            self.value = Value::Mapping(Mapping::new());
+        }
+        let Value::Mapping(entries) = other.value else {
+            return Ok(());
+        };
+        let map = self.value.as_mapping_mut().unwrap();
+        let origin = |name: &str| {
+            other
+                .origins
+                .get(name)
+                .cloned()
+                .or_else(|| file.map(Path::to_path_buf))
+        };
+        let duplicate = |name: &str| {
+            let files: Vec<_> = [self.origins.get(name).cloned(), origin(name)]
+                .into_iter()
+                .flatten()
+                .map(|p| p.display().to_string())
+                .collect();
+            match files.is_empty() {
+                true => anyhow!("{} is defined more than once", name),
+                false => anyhow!("{} is defined more than once ({})", name, files.join(", ")),
+            }
+        };
+
+        let mut names = vec![];
+        for (key, value) in entries {
+            let name = key.as_str().unwrap_or_default().to_string();
+            if SECTIONS.contains(&name.as_str()) {
+                let section = map
+                    .entry(key)
+                    .or_insert_with(|| Value::Mapping(Mapping::new()));
+                for (k, v) in value.as_mapping().cloned().unwrap_or_default() {
+                    let entry = format!("{}.{}", name, k.as_str().unwrap_or_default());
+                    if section.get(&k).is_some() {
+                        return Err(duplicate(&entry));
+                    }
+                    section.as_mapping_mut().unwrap().insert(k, v);
+                    names.push(entry);
+                }
+            } else {
+                if map.contains_key(&key) {
+                    return Err(duplicate(&name));
+                }
+                map.insert(key, value);
+                names.push(name);
+            }
+        }
+        for name in names {
+            if let Some(file) = origin(&name) {
+                self.origins.insert(name, file);
+            }
+        }
+        Ok(())
+    }
+
+    /// merge the `fragments` an item `extends` under the item
+    fn resolve_extends(&mut self) -> Result<()> {
+        let Some(map) = self.value.as_mapping_mut() else {
+            return Ok(());
+        };
+        let fragments = match map.shift_remove("fragments") {
+            Some(Value::Mapping(fragments)) => fragments,
+            Some(_) => return Err(anyhow!("fragments must be a mapping")),
+            None => Mapping::new(),
+        };
+        let mut rewritten = !fragments.is_empty();
+        for (name, item) in map.iter_mut() {
+            let name = name.as_str().unwrap_or_default();
+            if SECTIONS.contains(&name) || !has_extends(item) {
+                continue;
+            }
+            let mut stack = vec![];
+            *item = extend(item, &fragments, &mut stack).with_context(|| {
+                match self.origins.get(name) {
+                    Some(file) => format!("item {} in {}", name, file.display()),
+                    None => format!("item {}", name),
+                }
+            })?;
+            rewritten = true;
+        }
+        if rewritten {
+            self.source = None;
+        }
+        Ok(())
+    }
+
+    /// relative schema paths are relative to the file of the item, not the current directory
+    fn resolve_schemas(&mut self) {
+        let Some(map) = self.value.as_mapping_mut() else {
+            return;
+        };
+        for (name, item) in map.iter_mut() {
+            let name = name.as_str().unwrap_or_default();
+            let dir = match self.origins.get(name).and_then(|file| file.parent()) {
+                Some(dir) if !SECTIONS.contains(&name) && !dir.as_os_str().is_empty() => dir,
+                _ => continue,
+            };
+            for fields in SCHEMA_FIELDS {
+                let mut value = Some(&mut *item);
+                for field in fields {
+                    value = value.and_then(|v| v.get_mut(field));
+                }
+                if let Some(Value::String(path)) = value {
+                    if Path::new(path).is_relative() {
+                        *path = dir.join(&*path).display().to_string();
+                        self.source = None;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn load(path: &Path, format: Option<ConfigFormat>, stack: &mut Vec<PathBuf>) -> Result<Document> {
+    let canonical = path
+        .canonicalize()
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    if let Some(pos) = stack.iter().position(|p| p == &canonical) {
+        let mut cycle: Vec<_> = stack[pos..]
+            .iter()
+            .map(|p| p.display().to_string())
+            .collect();
+        cycle.push(canonical.display().to_string());
+        return Err(anyhow!("include cycle: {}", cycle.join(" -> ")));
+    }
+    let content =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+    stack.push(canonical);
+    let format = format.unwrap_or_else(|| ConfigFormat::from_path(path));
+    let doc = parse(&content, format, Some(path), dir, stack);
+    stack.pop();
+    doc
+}
+
+fn parse(
+    content: &str,
+    format: ConfigFormat,
+    file: Option<&Path>,
+    dir: &Path,
+    stack: &mut Vec<PathBuf>,
+) -> Result<Document> {
+    let name = file.map_or("config".to_string(), |f| f.display().to_string());
+    let mut value = format
+        .parse(content)
+        .with_context(|| format!("failed to parse {}", name))?;
+    let include = value
+        .as_mapping_mut()
+        .and_then(|m| m.shift_remove("include"));
+    let Some(include) = include else {
+        // only yaml can be deserialized from the text again
+        let mut doc = Document {
+            source: (format == ConfigFormat::Yaml).then(|| content.to_string()),
+            ..Default::default()
+        };
+        doc.merge(
+            Document {
+                value,
+                ..Default::default()
+            },
+            file,
+        )?;
+        return Ok(doc);
+    };
+
+    let patterns = match include {
+        Value::String(s) => vec![s],
+        Value::Sequence(seq) => seq
+            .into_iter()
+            .map(|v| match v {
+                Value::String(s) => Ok(s),
+                _ => Err(anyhow!("{}: include entries must be strings", name)),
+            })
+            .collect::<Result<_>>()?,
+        _ => return Err(anyhow!("{}: include must be a string or a list", name)),
+    };
+    let mut doc = Document::default();
+    for pattern in patterns {
+        for path in expand(dir, &pattern).with_context(|| format!("included from {}", name))? {
+            let child =
+                load(&path, None, stack).with_context(|| format!("included from {}", name))?;
+            doc.merge(child, None)?;
+        }
+    }
+    doc.merge(
+        Document {
+            value,
+            ..Default::default()
+        },
+        file,
+    )?;
+    Ok(doc)
+}
+
+/// the files matching an include pattern relative to `dir`, sorted by name
+fn expand(dir: &Path, pattern: &str) -> Result<Vec<PathBuf>> {
+    let path = dir.join(pattern);
+    if !pattern.contains(['*', '?', '[']) {
+        return Ok(vec![path]);
+    }
+    let pattern = path.to_string_lossy();
+    let mut paths = glob::glob(&pattern)
+        .with_context(|| format!("invalid include pattern {}", pattern))?
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.sort();
+    Ok(paths)
+}
+
+fn has_extends(value: &Value) -> bool {
+    value
+        .as_mapping()
+        .is_some_and(|m| m.contains_key("extends"))
+}
+
+/// resolve `extends` of a mapping, the fragments are applied in order and the
+/// mapping itself is merged last
+fn extend(value: &Value, fragments: &Mapping, stack: &mut Vec<String>) -> Result<Value> {
+    let mut value = value.clone();
+    let names = match value
+        .as_mapping_mut()
+        .and_then(|m| m.shift_remove("extends"))
+    {
+        Some(Value::String(s)) => vec![s],
+        Some(Value::Sequence(seq)) => seq
+            .into_iter()
+            .map(|v| match v {
+                Value::String(s) => Ok(s),
+                _ => Err(anyhow!("extends entries must be strings")),
+            })
+            .collect::<Result<_>>()?,
+        Some(_) => return Err(anyhow!("extends must be a string or a list")),
+        None => return Ok(value),
+    };
+    let mut base = Value::Mapping(Mapping::new());
+    for name in names {
+        if stack.contains(&name) {
+            stack.push(name);
+            return Err(anyhow!("fragment cycle: {}", stack.join(" -> ")));
+        }
+        let fragment = fragments
+            .get(name.as_str())
+            .ok_or_else(|| anyhow!("unknown fragment {}", name))?;
+        stack.push(name);
+        let fragment = extend(fragment, fragments, stack)?;
+        stack.pop();
+        merge_patch(&mut base, fragment);
+    }
+    merge_patch(&mut base, value);
+    Ok(base)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Load, RequestConfig};
+
+    fn dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("xdiff-include-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (file, content) in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn include_should_merge_files() {
+        let dir = dir(
+            "merge",
+            &[
+                ("main.yml", "include: [items/*.yml, env.yml]\nc: 3\n"),
+                ("items/a.yml", "a: 1\n"),
+                ("items/b.yml", "b: 2\nenvironments: {prod: {}}\n"),
+                ("env.yml", "environments: {staging: {}}\n"),
+            ],
+        );
+        let doc = Document::load_file(&dir.join("main.yml"), None).unwrap();
+        let expected: Value =
+            serde_yaml::from_str("a: 1\nb: 2\nenvironments: {prod: {}, staging: {}}\nc: 3\n")
+                .unwrap();
+        assert_eq!(doc.value, expected);
+        assert!(doc.source.is_none());
+        assert_eq!(doc.origins["a"], dir.join("items/a.yml"));
+        assert_eq!(doc.origins["c"], dir.join("main.yml"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn include_should_detect_errors() {
+        let dir = dir(
+            "errors",
+            &[
+                ("cycle.yml", "include: other.yml\n"),
+                ("other.yml", "include: cycle.yml\n"),
+                ("dup.yml", "include: a.yml\na: 2\n"),
+                ("a.yml", "a: 1\n"),
+            ],
+        );
+        let err = Document::load_file(&dir.join("cycle.yml"), None).unwrap_err();
+        assert!(format!("{:#}", err).contains("include cycle"));
+        let err = Document::load_file(&dir.join("dup.yml"), None).unwrap_err();
+        assert!(err.to_string().contains("a is defined more than once"));
+        assert!(err.to_string().contains("a.yml"));
+        let err = Document::load_file(&dir.join("missing.yml"), None).unwrap_err();
+        assert!(err.to_string().contains("missing.yml"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn load_should_name_the_file_of_an_invalid_item() {
+        let dir = dir(
+            "invalid",
+            &[
+                (
+                    "main.yml",
+                    "include: items/*.yml
+b: {url: 'https://b.com/'}
+",
+                ),
+                (
+                    "items/a.yml",
+                    "a:
+  url: https://a.com/
+  method: [1]
+",
+                ),
+            ],
+        );
+        let path = dir.join("main.yml");
+        let err = RequestConfig::load(path.to_str().unwrap(), None)
+            .await
+            .unwrap_err();
+        let message = format!("{:#}", err);
+        assert!(message.contains("item: a"), "{}", message);
+        assert!(message.contains("items/a.yml"), "{}", message);
+        assert!(
+            message.contains("expected valid method name"),
+            "{}",
+            message
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn extends_should_work() {
+        let doc = Document::load_str(
+            r#"
+fragments:
+  base:
+    headers: {accept: application/json}
+    params: {a: 1}
+  v2:
+    extends: base
+    params: {version: 2}
+todo:
+  extends: v2
+  params: {a: ~, id: 1}
+"#,
+            ConfigFormat::Yaml,
+        )
+        .unwrap();
+        let expected: Value = serde_yaml::from_str(
+            "todo:\n  headers: {accept: application/json}\n  params: {version: 2, id: 1}\n",
+        )
+        .unwrap();
+        assert_eq!(doc.value, expected);
+
+        let err = Document::load_str(
+            "fragments:\n  a: {extends: b}\n  b: {extends: a}\ntodo: {extends: a}\n",
+            ConfigFormat::Yaml,
+        )
+        .unwrap_err();
+        assert!(format!("{:#}", err).contains("fragment cycle: a -> b -> a"));
+        assert!(Document::load_str("todo: {extends: missing}\n", ConfigFormat::Yaml).is_err());
+    }
+
+    #[test]
+    fn schema_paths_should_be_relative_to_the_item_file() {
+        let dir = dir(
+            "schema",
+            &[
+                (
+                    "main.yml",
+                    "include: items/a.yml
+b: {schema: b.json}
+",
+                ),
+                (
+                    "items/a.yml",
+                    "a: {res: {schema: a.json}}
+c: {schema: /c.json}
+",
+                ),
+            ],
+        );
+        let doc = Document::load_file(&dir.join("main.yml"), None).unwrap();
+        let path = |file: &str| Value::from(dir.join(file).display().to_string());
+        assert_eq!(doc.value["a"]["res"]["schema"], path("items/a.json"));
+        assert_eq!(doc.value["b"]["schema"], path("b.json"));
+        assert_eq!(doc.value["c"]["schema"], Value::from("/c.json"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
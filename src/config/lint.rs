@@ -0,0 +1,520 @@
+use std::{collections::HashMap, fmt, fs, path::Path};
+
+use indexmap::IndexMap;
+use serde::de::DeserializeOwned;
+use serde_yaml::Value;
+use toml_edit::{ImDocument, Item, TableLike};
+use yaml_rust2::{parser::Parser, Event};
+
+use super::{include::Document, ConfigFormat, Load};
+use crate::context::Environment;
+
+/// a problem of a config, located in the file it was defined in if possible
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    pub file: Option<String>,
+    /// 1-based line and column of the field
+    pub location: Option<(usize, usize)>,
+    /// dot separated path of the field, e.g. `todo.req1.url`
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file)?;
+            if let Some((line, column)) = self.location {
+                write!(f, "{}:{}:", line, column)?;
+            }
+            write!(f, " ")?;
+        }
+        if !self.path.is_empty() {
+            write!(f, "{}: ", self.path)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// problems of a part of a config, the paths are relative to the part
+#[derive(Debug, Default)]
+pub(crate) struct Issues(Vec<(String, String)>);
+
+impl Issues {
+    pub(crate) fn add(&mut self, path: impl Into<String>, message: impl fmt::Display) {
+        self.0.push((path.into(), message.to_string()));
+    }
+
+    /// add the issues of a field of the part
+    pub(crate) fn extend(&mut self, prefix: &str, other: Issues) {
+        for (path, message) in other.0 {
+            self.0.push((join(prefix, &path), message));
+        }
+    }
+
+    /// add the first line of an error of the existing validation
+    pub(crate) fn add_error(&mut self, path: impl Into<String>, err: anyhow::Error) {
+        let message = format!("{:#}", err);
+        self.add(path, message.lines().next().unwrap_or_default());
+    }
+
+    /// deserialize a part of the config, the fields it doesn't know and the
+    /// deserialization error are reported
+    pub(crate) fn deserialize<T: DeserializeOwned>(&mut self, value: &Value) -> Option<T> {
+        let mut unknown = vec![];
+        let mut callback = |path: serde_ignored::Path| unknown.push(normalize(&path.to_string()));
+        let de = serde_ignored::Deserializer::new(value.clone(), &mut callback);
+        let result: Result<T, _> = serde_path_to_error::deserialize(de);
+        for path in unknown {
+            self.add(path, "unknown field");
+        }
+        match result {
+            Ok(v) => Some(v),
+            Err(e) => {
+                let path = normalize(&e.path().to_string());
+                self.add(path, e.into_inner());
+                None
+            }
+        }
+    }
+}
+
+/// check every item of a config file and report all the problems at once
+pub(crate) fn lint_file<T: Load>(
+    path: &str,
+    format: Option<ConfigFormat>,
+    lint_item: fn(&Value, &IndexMap<String, Environment>) -> Issues,
+) -> Vec<Problem> {
+    let doc = match Document::load_file(Path::new(path), format) {
+        Ok(doc) => doc,
+        Err(e) => return vec![load_problem(path, e)],
+    };
+    let value = match T::resolve(doc.value.clone()) {
+        Ok(value) => value,
+        Err(e) => return vec![load_problem(path, e)],
+    };
+    let Some(map) = value.as_mapping() else {
+        return vec![load_problem(
+            path,
+            anyhow::anyhow!("config must be a mapping"),
+        )];
+    };
+
+    let mut issues = Issues::default();
+    let mut environments = IndexMap::new();
+    for (name, env) in map
+        .get("environments")
+        .and_then(|v| v.as_mapping())
+        .into_iter()
+        .flatten()
+    {
+        let name = name.as_str().unwrap_or_default();
+        let mut env_issues = Issues::default();
+        if let Some(env) = env_issues.deserialize::<Environment>(env) {
+            if let Err(e) = env.validate() {
+                env_issues.add_error("base_url", e);
+            }
+            environments.insert(name.to_string(), env);
+        }
+        issues.extend(&format!("environments.{}", name), env_issues);
+    }
+    for (name, item) in map {
+        let name = name.as_str().unwrap_or_default();
+        if name != "environments" {
+            issues.extend(name, lint_item(item, &environments));
+        }
+    }
+
+    let mut locator = Locator::default();
+    let mut problems: Vec<_> = issues
+        .0
+        .into_iter()
+        .map(|(field, message)| {
+            let file = origin(&doc, &field).unwrap_or(path.to_string());
+            Problem {
+                location: locator.locate(&file, &field),
+                file: Some(file),
+                path: field,
+                message,
+            }
+        })
+        .collect();
+    problems.sort_by(|a, b| (&a.file, a.location).cmp(&(&b.file, b.location)));
+    problems
+}
+
+fn load_problem(path: &str, err: anyhow::Error) -> Problem {
+    let location = err
+        .chain()
+        .find_map(|e| e.downcast_ref::<serde_yaml::Error>())
+        .and_then(|e| e.location())
+        .map(|l| (l.line(), l.column()));
+    Problem {
+        file: Some(path.to_string()),
+        location,
+        path: String::new(),
+        message: format!("{:#}", err),
+    }
+}
+
+/// the file an item or an environment was included from
+fn origin(doc: &Document, path: &str) -> Option<String> {
+    let mut parts = path.splitn(3, '.');
+    let first = parts.next().unwrap_or_default();
+    let name = match (first, parts.next()) {
+        ("environments", Some(env)) => format!("environments.{}", env),
+        _ => first.to_string(),
+    };
+    doc.origin(&name).map(|p| p.display().to_string())
+}
+
+/// line and column of the fields of the config files, loaded on demand
+#[derive(Default)]
+struct Locator(HashMap<String, HashMap<String, (usize, usize)>>);
+
+impl Locator {
+    /// the location of the field, or of its closest parent defined in the file
+    /// when the field was generated, e.g. by a patch
+    fn locate(&mut self, file: &str, path: &str) -> Option<(usize, usize)> {
+        let locations = self.0.entry(file.to_string()).or_insert_with(|| {
+            let format = ConfigFormat::from_path(Path::new(file));
+            fs::read_to_string(file)
+                .map(|content| locations(&content, format))
+                .unwrap_or_default()
+        });
+        let mut path = path;
+        loop {
+            if let Some(location) = locations.get(path) {
+                return Some(*location);
+            }
+            path = path.rsplit_once('.')?.0;
+        }
+    }
+}
+
+/// 1-based line and column of every key and sequence element by dot separated path
+pub(crate) fn locations(content: &str, format: ConfigFormat) -> HashMap<String, (usize, usize)> {
+    match format {
+        ConfigFormat::Toml => toml_locations(content),
+        // json is a subset of yaml
+        ConfigFormat::Yaml | ConfigFormat::Json => yaml_locations(content),
+    }
+}
+
+enum Frame {
+    /// the key of the value being parsed, `None` if a key is expected
+    Map(Option<String>),
+    /// the index of the element being parsed
+    Seq(usize),
+}
+
+fn yaml_locations(content: &str) -> HashMap<String, (usize, usize)> {
+    let mut locations = HashMap::new();
+    let mut parser = Parser::new_from_str(content);
+    let mut frames: Vec<Frame> = vec![];
+    let mut path: Vec<String> = vec![];
+
+    while let Ok((event, marker)) = parser.next_token() {
+        let location = (marker.line(), marker.col() + 1);
+        // the path segment of a value starting in the current frame
+        let segment = match frames.last() {
+            Some(Frame::Map(Some(key))) => Some(key.clone()),
+            Some(Frame::Seq(idx)) => Some(idx.to_string()),
+            _ => None,
+        };
+        match &event {
+            Event::StreamEnd => break,
+            Event::Scalar(key, ..) if matches!(frames.last(), Some(Frame::Map(None))) => {
+                let parent = path.join(".");
+                // a mapping in a sequence starts at its first key
+                locations.entry(parent.clone()).or_insert(location);
+                locations.insert(join(&parent, key), location);
+                if let Some(frame) = frames.last_mut() {
+                    *frame = Frame::Map(Some(key.clone()));
+                }
+            }
+            Event::Scalar(..) | Event::Alias(..) | Event::SequenceStart(..)
+                if matches!(frames.last(), Some(Frame::Seq(_))) =>
+            {
+                let element = join(&path.join("."), segment.as_deref().unwrap_or_default());
+                locations.insert(element, location);
+            }
+            _ => {}
+        }
+        match event {
+            Event::Scalar(..) | Event::Alias(..) if segment.is_some() => end(&mut frames),
+            Event::MappingStart(..) | Event::SequenceStart(..) => {
+                path.extend(segment);
+                frames.push(match event {
+                    Event::MappingStart(..) => Frame::Map(None),
+                    _ => Frame::Seq(0),
+                });
+            }
+            Event::MappingEnd | Event::SequenceEnd => {
+                frames.pop();
+                if !frames.is_empty() {
+                    path.pop();
+                    end(&mut frames);
+                }
+            }
+            _ => {}
+        }
+    }
+    locations
+}
+
+/// a value of the current frame was parsed
+fn end(frames: &mut [Frame]) {
+    match frames.last_mut() {
+        Some(Frame::Map(key)) => *key = None,
+        Some(Frame::Seq(idx)) => *idx += 1,
+        None => {}
+    }
+}
+
+fn toml_locations(content: &str) -> HashMap<String, (usize, usize)> {
+    let mut locations = HashMap::new();
+    if let Ok(doc) = ImDocument::parse(content) {
+        toml_table(content, doc.as_table(), "", &mut locations);
+    }
+    locations
+}
+
+fn toml_table(
+    content: &str,
+    table: &dyn TableLike,
+    path: &str,
+    locations: &mut HashMap<String, (usize, usize)>,
+) {
+    for (name, _) in table.iter() {
+        let Some((key, item)) = table.get_key_value(name) else {
+            continue;
+        };
+        let path = join(path, name);
+        if let Some(span) = key.span() {
+            locations.insert(path.clone(), position(content, span.start));
+        }
+        toml_item(content, item, &path, locations);
+    }
+}
+
+fn toml_item(
+    content: &str,
+    item: &Item,
+    path: &str,
+    locations: &mut HashMap<String, (usize, usize)>,
+) {
+    if let Some(table) = item.as_table_like() {
+        toml_table(content, table, path, locations);
+    } else if let Some(array) = item.as_array() {
+        for (idx, value) in array.iter().enumerate() {
+            let path = join(path, &idx.to_string());
+            if let Some(span) = value.span() {
+                locations.insert(path.clone(), position(content, span.start));
+            }
+            if let Some(table) = value.as_inline_table() {
+                toml_table(content, table, &path, locations);
+            }
+        }
+    } else if let Some(tables) = item.as_array_of_tables() {
+        for (idx, table) in tables.iter().enumerate() {
+            let path = join(path, &idx.to_string());
+            if let Some(span) = table.span() {
+                locations.insert(path.clone(), position(content, span.start));
+            }
+            toml_table(content, table, &path, locations);
+        }
+    }
+}
+
+/// 1-based line and column of a byte offset
+fn position(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before
+        .rsplit('\n')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .count()
+        + 1;
+    (line, column)
+}
+
+fn join(prefix: &str, path: &str) -> String {
+    match (prefix.is_empty(), path.is_empty()) {
+        (true, _) => path.to_string(),
+        (_, true) => prefix.to_string(),
+        _ => format!("{}.{}", prefix, path),
+    }
+}
+
+/// turn the `a.b[0].c` paths of serde into `a.b.0.c`
+fn normalize(path: &str) -> String {
+    let path = path.replace('[', ".").replace(']', "");
+    // serde_ignored writes `?` for the value of an option
+    path.split('.')
+        .filter(|s| !s.is_empty() && *s != "?")
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DiffConfig, RequestConfig};
+
+    /// a temp dir for the configs of a test, removed when dropped
+    struct TempDir(std::path::PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("xdiff-lint-{}-{}", name, std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        fn write(&self, name: &str, content: &str) -> String {
+            let path = self.0.join(name);
+            fs::write(&path, content).unwrap();
+            path.display().to_string()
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn summary(problems: &[Problem]) -> Vec<String> {
+        problems
+            .iter()
+            .map(|p| {
+                let (line, column) = p.location.unwrap_or_default();
+                format!("{}:{} {}: {}", line, column, p.path, p.message)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn locations_should_work() {
+        let yaml = "a:\n  b: 1\n  c:\n    - x\n    - d: 2\n";
+        let locations = locations(yaml, ConfigFormat::Yaml);
+        assert_eq!(locations["a"], (1, 1));
+        assert_eq!(locations["a.b"], (2, 3));
+        assert_eq!(locations["a.c.1"], (5, 7));
+        assert_eq!(locations["a.c.1.d"], (5, 7));
+
+        let json = "{\n  \"a\": {\"b\": [1, {\"d\": 2}]}\n}\n";
+        let locations = super::locations(json, ConfigFormat::Json);
+        assert_eq!(locations["a.b"], (2, 9));
+        assert_eq!(locations["a.b.1.d"], (2, 19));
+
+        let toml = "[a]\nb = 1\n\n[a.c]\nd = { e = 2 }\n";
+        let locations = super::locations(toml, ConfigFormat::Toml);
+        assert_eq!(locations["a.b"], (2, 1));
+        assert_eq!(locations["a.c.d.e"], (5, 7));
+    }
+
+    #[test]
+    fn lint_should_report_all_problems() {
+        let dir = TempDir::new("all");
+        let path = dir.write(
+            "xdiff.yaml",
+            r#"
+environments:
+  staging:
+    base_url: https://staging.example.com
+    variables:
+      id: "1"
+  prod:
+    base_url: https://example.com
+todo:
+  req1:
+    url: ftp://example.com/todos/1
+    body:
+      id: 1
+  req2:
+    method: POST
+    url: /todos/{{id}}
+    headers:
+      content-type: text/plain
+    timout: 1s
+  res:
+    skip_body:
+      - data..id
+"#,
+        );
+        let problems = DiffConfig::lint(&path, None);
+        assert_eq!(
+            summary(&problems),
+            vec![
+                "11:5 todo.req1.url: unsupported scheme ftp, expected http or https",
+                "12:5 todo.req1.body: GET requests should not have a body",
+                "16:5 todo.req2.url: undefined variable id in environment prod",
+                "18:7 todo.req2.headers.content-type: unsupported content-type text/plain, \
+                 the body is sent as application/json",
+                "19:5 todo.req2.timout: unknown field",
+                "22:9 todo.res.skip_body.0: invalid json path \"data..id\"",
+            ]
+        );
+        assert_eq!(
+            problems[4].to_string(),
+            format!("{}:19:5: todo.req2.timout: unknown field", path)
+        );
+    }
+
+    #[test]
+    fn lint_should_report_invalid_values_and_files() {
+        let dir = TempDir::new("values");
+        let path = dir.write(
+            "xreq.toml",
+            "[todo]\nurl = \"https://example.com\"\nmethod = \"GET\"\n\n[todo.assert]\nstatus = [\"ok\"]\n",
+        );
+        let problems = RequestConfig::lint(&path, None);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].path, "todo.assert.status.0");
+        assert_eq!(problems[0].location, Some((6, 11)));
+
+        let path = dir.write("broken.yaml", "todo:\n  url: [\n");
+        let problems = RequestConfig::lint(&path, None);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].location.is_some());
+        assert!(DiffConfig::lint("fixtures/test.yaml", None).is_empty());
+        assert!(RequestConfig::lint("fixtures/xreq.yaml", None).is_empty());
+    }
+
+    #[test]
+    fn lint_should_check_the_parts_of_an_item_independently() {
+        let dir = TempDir::new("parts");
+        let path = dir.write(
+            "parts.yaml",
+            r#"todo:
+  req1:
+    url: https://example.com/
+    headers:
+      x-bad: "a\nb"
+  req2:
+    url: https://exa mple.com/
+  res:
+    skip_headers: ["bad header"]
+  retires: 1
+"#,
+        );
+        let problems = DiffConfig::lint(&path, None);
+        let paths: Vec<_> = problems.iter().map(|p| p.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "todo.req1.headers",
+                "todo.req2",
+                "todo.res.skip_headers.0",
+                "todo.retires"
+            ],
+            "{:?}",
+            summary(&problems)
+        );
+    }
+}
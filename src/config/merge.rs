@@ -0,0 +1,194 @@
+use anyhow::{anyhow, Context, Result};
+use serde_yaml::{Mapping, Value};
+use url::Url;
+
+/// apply a json merge patch (RFC 7386): mappings are merged recursively, a null
+/// removes the key and any other value replaces the target
+pub(crate) fn merge_patch(target: &mut Value, patch: Value) {
+    match patch {
+        Value::Mapping(patch) => {
+            if !target.is_mapping() {
+                *target = Value::Mapping(Mapping::new());
+            }
+            let map = target.as_mapping_mut().unwrap();
+            for (k, v) in patch {
+                if v.is_null() {
+                    map.remove(&k);
+                } else {
+                    merge_patch(map.entry(k).or_insert(Value::Null), v);
+                }
+            }
+        }
+        patch => *target = patch,
+    }
+}
+
+/// fill the unset fields of a request with the defaults, a null in the request
+/// unsets a default
+pub(crate) fn apply_defaults(defaults: &Value, req: &mut Value) {
+    let mut merged = defaults.clone();
+    merge_patch(&mut merged, std::mem::take(req));
+    *req = merged;
+}
+
+/// the patch of a request written as `{patch: {...}}`
+pub(crate) fn request_patch(req: &Value) -> Option<&Value> {
+    req.as_mapping()
+        .filter(|m| m.len() == 1)
+        .and_then(|m| m.get("patch"))
+}
+
+/// build a request from `base` and a patch: `host` and `path` change the url,
+/// the other fields are merged into the base as a json merge patch
+pub(crate) fn patch_request(base: &Value, patch: &Value) -> Result<Value> {
+    let mut patch = patch.clone();
+    let Value::Mapping(fields) = &mut patch else {
+        return Err(anyhow!("patch must be a mapping"));
+    };
+    let host = take_str(fields, "host")?;
+    let path = take_str(fields, "path")?;
+
+    let mut req = base.clone();
+    lowercase_headers(&mut req);
+    lowercase_headers(&mut patch);
+    merge_patch(&mut req, patch);
+    if host.is_none() && path.is_none() {
+        return Ok(req);
+    }
+    let url = req
+        .get("url")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow!("patching host or path requires a url"))?;
+    let url = match Url::parse(url) {
+        Ok(mut url) => {
+            if let Some(host) = &host {
+                // the colons of an ipv6 host like `[::1]` are not a port
+                let (name, port) = match host.rsplit_once(':') {
+                    Some((name, port)) if !port.contains(']') => (name, Some(port.parse::<u16>()?)),
+                    _ => (host.as_str(), None),
+                };
+                url.set_host(Some(name))
+                    .with_context(|| format!("invalid host {}", host))?;
+                if port.is_some() {
+                    url.set_port(port)
+                        .map_err(|_| anyhow!("invalid host {}", host))?;
+                }
+            }
+            if let Some(path) = &path {
+                url.set_path(path);
+            }
+            url.to_string()
+        }
+        Err(_) if host.is_some() => {
+            return Err(anyhow!(
+                "patching the host requires an absolute url: {}",
+                url
+            ))
+        }
+        Err(_) => path.unwrap(),
+    };
+    req.as_mapping_mut()
+        .unwrap()
+        .insert("url".into(), Value::String(url));
+    Ok(req)
+}
+
+/// header names are case insensitive, lowercase them so that a patch matches the
+/// headers of the base whatever their case
+fn lowercase_headers(req: &mut Value) {
+    let Some(Value::Mapping(headers)) = req.get_mut("headers") else {
+        return;
+    };
+    *headers = std::mem::take(headers)
+        .into_iter()
+        .map(|(k, v)| match k {
+            Value::String(k) => (Value::String(k.to_lowercase()), v),
+            k => (k, v),
+        })
+        .collect();
+}
+
+fn take_str(map: &mut Mapping, key: &str) -> Result<Option<String>> {
+    match map.remove(key) {
+        Some(Value::String(s)) => Ok(Some(s)),
+        Some(_) => Err(anyhow!("{} must be a string", key)),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn yaml(s: &str) -> Value {
+        serde_yaml::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn merge_patch_should_work() {
+        let mut target = yaml("a: 1\nb: {c: 2, d: 3}\ne: [1]\n");
+        merge_patch(&mut target, yaml("a: ~\nb: {c: 4}\ne: [2]\nf: 5\n"));
+        assert_eq!(target, yaml("b: {c: 4, d: 3}\ne: [2]\nf: 5\n"));
+    }
+
+    #[test]
+    fn patch_request_should_work() {
+        let base = yaml(
+            r#"
+url: https://a.example.com/todos/1?x=1
+params: {a: 1, b: 2}
+headers: {x-a: "1", x-b: "2"}
+body: {user: {name: alice, age: 30}}
+"#,
+        );
+        let patch = yaml(
+            r#"
+host: b.example.com:8443
+path: /todos/2
+params: {b: ~, c: 3}
+headers: {x-a: ~}
+body: {user: {age: 31}}
+"#,
+        );
+        let req = patch_request(&base, &patch).unwrap();
+        assert_eq!(
+            req,
+            yaml(
+                r#"
+url: https://b.example.com:8443/todos/2?x=1
+params: {a: 1, c: 3}
+headers: {x-b: "2"}
+body: {user: {name: alice, age: 31}}
+"#
+            )
+        );
+        let relative = yaml("url: /todos/1\n");
+        assert_eq!(
+            patch_request(&relative, &yaml("path: /todos/2\n")).unwrap(),
+            yaml("url: /todos/2\n")
+        );
+        assert!(patch_request(&relative, &yaml("host: b.example.com\n")).is_err());
+    }
+
+    #[test]
+    fn patch_request_should_ignore_the_case_of_headers() {
+        let base = yaml("url: /todos\nheaders: {X-Api-Key: a, Accept: json}\n");
+        let patch = yaml("headers: {x-api-key: ~, ACCEPT: xml}\n");
+        assert_eq!(
+            patch_request(&base, &patch).unwrap(),
+            yaml("url: /todos\nheaders: {accept: xml}\n")
+        );
+    }
+
+    #[test]
+    fn patch_request_should_accept_ipv6_hosts() {
+        let base = yaml("url: http://localhost:8080/todos\n");
+        for (host, url) in [
+            ("'[::1]'", "http://[::1]:8080/todos"),
+            ("'[::1]:9090'", "http://[::1]:9090/todos"),
+        ] {
+            let req = patch_request(&base, &yaml(&format!("host: {}\n", host))).unwrap();
+            assert_eq!(req["url"], url);
+        }
+    }
+}
@@ -1,7 +1,26 @@
+use std::{fmt, path::PathBuf};
+
 use anyhow::Result;
 use async_trait::async_trait;
 use serde::de::DeserializeOwned;
 
+use include::Document;
+
+pub use edit::ConfigEditor;
+pub use filter::ItemFilter;
+pub(crate) use filter::Tagged;
+pub use format::ConfigFormat;
+pub(crate) use lint::Issues;
+pub use lint::Problem;
+pub(crate) use schema::json_schema;
+
+mod edit;
+mod filter;
+mod format;
+mod include;
+mod lint;
+mod merge;
+pub(crate) mod schema;
 pub mod xdiff;
 pub mod xreq;
 // pub use xdiff::{DiffConfig, DiffItem};
@@ -12,13 +31,24 @@ pub trait Load
 where
     Self: Sized + Validate + DeserializeOwned,
 {
+    /// load config from a yaml, json or toml file, the format is detected from the
+    /// extension unless given. `include` entries are relative to the file
+    async fn load(path: &str, format: Option<ConfigFormat>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        // includes and globs read many files, keep them off the runtime threads
+        let path = PathBuf::from(path);
+        let doc = tokio::task::spawn_blocking(move || Document::load_file(&path, format)).await??;
+        from_document(doc)
+    }
+
     /// load config from yaml file
     async fn load_yaml(path: &str) -> Result<Self>
     where
         Self: Sized,
     {
-        let content = tokio::fs::read_to_string(path).await?;
-        Self::from_yaml(&content)
+        Self::load(path, Some(ConfigFormat::Yaml)).await
     }
 
     /// load config from yaml string
@@ -26,9 +56,61 @@ where
     where
         Self: Sized,
     {
-        let config: Self = serde_yaml::from_str(content)?;
-        config.validate()?;
-        Ok(config)
+        from_document(Document::load_str(content, ConfigFormat::Yaml)?)
+    }
+
+    /// load config from json string
+    fn from_json(content: &str) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        from_document(Document::load_str(content, ConfigFormat::Json)?)
+    }
+
+    /// load config from toml string
+    fn from_toml(content: &str) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        from_document(Document::load_str(content, ConfigFormat::Toml)?)
+    }
+
+    /// rewrite the raw config before it is deserialized, e.g. to apply defaults
+    fn resolve(value: serde_yaml::Value) -> Result<serde_yaml::Value> {
+        Ok(value)
+    }
+}
+
+fn from_document<T: Load>(doc: Document) -> Result<T> {
+    let resolved = T::resolve(doc.value.clone())?;
+    // deserialize the text when nothing was rewritten to keep the error locations
+    let config: T = match &doc.source {
+        Some(source) if resolved == doc.value => serde_yaml::from_str(source)?,
+        _ => serde_yaml::from_value(resolved.clone())
+            .map_err(|e| doc.attribute(item_error::<T>(&resolved).unwrap_or(e.into())))?,
+    };
+    config.validate().map_err(|e| doc.attribute(e))?;
+    Ok(config)
+}
+
+/// deserialize the entries one by one to find the item which failed, so the error can
+/// name it and the file it is defined in
+fn item_error<T: Load>(value: &serde_yaml::Value) -> Option<anyhow::Error> {
+    value.as_mapping()?.iter().find_map(|(name, item)| {
+        let single = serde_yaml::Mapping::from_iter([(name.clone(), item.clone())]);
+        let err = serde_yaml::from_value::<T>(serde_yaml::Value::Mapping(single)).err()?;
+        let name = name.as_str().unwrap_or_default().to_string();
+        Some(anyhow::Error::new(err).context(ItemError(name)))
+    })
+}
+
+/// context of an error in a config item, the loader adds the file of the item to it
+#[derive(Debug)]
+pub(crate) struct ItemError(pub(crate) String);
+
+impl fmt::Display for ItemError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to validate item: {}", self.0)
     }
 }
 
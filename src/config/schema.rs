@@ -0,0 +1,186 @@
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde_json::{json, Value};
+
+use super::{
+    xdiff::{DiffConfig, DiffItem},
+    xreq::{RequestConfig, RequestItem},
+};
+use crate::RequestContext;
+
+/// the json schema of a `xdiff` config
+pub(crate) fn diff_config() -> Value {
+    let mut schema = generate::<DiffConfig, DiffItem>();
+    add_sections(&mut schema, "DiffItem");
+    let patch = json!({
+        "type": "object",
+        "description": "req1 with a json merge patch applied, `host` and `path` change its url",
+        "required": ["patch"],
+        "additionalProperties": false,
+        "properties": {
+            // a null removes a field of req1, so the fields are not checked
+            "patch": {
+                "type": "object",
+                "properties": {
+                    "host": {"type": "string"},
+                    "path": {"type": "string"},
+                },
+            },
+        },
+    });
+    let req2 = &mut schema["definitions"]["DiffItem"]["properties"]["req2"];
+    *req2 = json!({"anyOf": [req2.take(), patch]});
+    schema
+}
+
+/// the json schema of a `xreq` config
+pub(crate) fn request_config() -> Value {
+    let mut schema = generate::<RequestConfig, RequestItem>();
+    add_sections(&mut schema, "RequestItem");
+    schema
+}
+
+/// the items are flattened into the config, so they are added as its other properties
+fn generate<T: JsonSchema, I: JsonSchema>() -> Value {
+    let mut gen = SchemaGenerator::default();
+    let item = gen.subschema_for::<I>();
+    // a request flattened into the items is not a definition of its own
+    gen.subschema_for::<RequestContext>();
+    let mut schema = serde_json::to_value(gen.into_root_schema_for::<T>()).unwrap();
+    deny_unknown_fields(&mut schema["definitions"]);
+    schema["additionalProperties"] = serde_json::to_value(item).unwrap();
+    schema
+}
+
+/// the loader rejects unknown fields, so the objects of the schema don't allow them either
+fn deny_unknown_fields(schema: &mut Value) {
+    match schema {
+        Value::Object(obj) => {
+            if obj.get("type") == Some(&json!("object"))
+                && obj.contains_key("properties")
+                && !obj.contains_key("additionalProperties")
+            {
+                obj.insert("additionalProperties".into(), false.into());
+            }
+            obj.values_mut().for_each(deny_unknown_fields);
+        }
+        Value::Array(items) => items.iter_mut().for_each(deny_unknown_fields),
+        _ => {}
+    }
+}
+
+/// the sections the loader resolves before the config is deserialized
+fn add_sections(schema: &mut Value, item: &str) {
+    let names = json!({
+        "anyOf": [
+            {"type": "string"},
+            {"type": "array", "items": {"type": "string"}},
+        ],
+    });
+    let mut defaults = schema["definitions"]["RequestContext"].clone();
+    if let Some(obj) = defaults.as_object_mut() {
+        obj.remove("required");
+        obj.insert(
+            "description".into(),
+            "fields every request gets unless it sets them itself".into(),
+        );
+    }
+    schema["definitions"]["RequestDefaults"] = defaults;
+
+    let properties = &mut schema["properties"];
+    properties["include"] = json!({
+        "description": "config files merged into this one, relative to it, globs are supported",
+        "allOf": [names.clone()],
+    });
+    properties["fragments"] = json!({
+        "description": "shared parts of items, merged into the items which extend them",
+        "type": "object",
+        "additionalProperties": {"type": "object"},
+    });
+    properties["defaults"] = json!({"$ref": "#/definitions/RequestDefaults"});
+    schema["definitions"][item]["properties"]["extends"] = json!({
+        "description": "fragments merged under the item, in order",
+        "allOf": [names],
+    });
+}
+
+/// a schema written as json, for the types serialized by hand
+pub(crate) fn json_schema(value: Value) -> Schema {
+    serde_json::from_value(value).unwrap()
+}
+
+pub(crate) fn method(_: &mut SchemaGenerator) -> Schema {
+    json_schema(json!({
+        "type": "string",
+        "enum": ["GET", "POST", "PUT", "PATCH", "DELETE", "HEAD", "OPTIONS", "TRACE", "CONNECT"],
+    }))
+}
+
+pub(crate) fn header_map(_: &mut SchemaGenerator) -> Schema {
+    json_schema(json!({
+        "type": "object",
+        "additionalProperties": {
+            "anyOf": [
+                {"type": "string"},
+                {"type": "array", "items": {"type": "string"}},
+            ],
+        },
+    }))
+}
+
+pub(crate) fn duration(_: &mut SchemaGenerator) -> Schema {
+    json_schema(json!({
+        "type": "string",
+        "description": "a duration like `500ms`, `2s` or `1m 30s`",
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DiffConfig, Load, RequestConfig};
+
+    fn validate(schema: &Value, path: &str) {
+        let content = std::fs::read_to_string(path).unwrap();
+        let config: Value = serde_yaml::from_str(&content).unwrap();
+        let validator = jsonschema::validator_for(schema).unwrap();
+        let errors: Vec<_> = validator
+            .iter_errors(&config)
+            .map(|e| format!("{}: {}", e.instance_path, e))
+            .collect();
+        assert!(errors.is_empty(), "{}: {:?}", path, errors);
+    }
+
+    #[tokio::test]
+    async fn schema_should_accept_the_fixtures() {
+        let schema = diff_config();
+        for path in ["fixtures/test.yaml", "fixtures/split.yaml"] {
+            validate(&schema, path);
+        }
+        validate(&request_config(), "fixtures/xreq.yaml");
+        assert!(DiffConfig::load_yaml("fixtures/test.yaml").await.is_ok());
+        assert!(RequestConfig::load_yaml("fixtures/xreq.yaml").await.is_ok());
+    }
+
+    #[test]
+    fn schema_should_reject_invalid_configs() {
+        let schema = diff_config();
+        let validator = jsonschema::validator_for(&schema).unwrap();
+        let config = json!({"todo": {"req1": {"url": "/a", "method": "FETCH"}}});
+        assert!(!validator.is_valid(&config));
+        let config = json!({"todo": {"req1": {"url": "/a", "timeout": {"connect": 1}}}});
+        assert!(!validator.is_valid(&config));
+        let config =
+            json!({"todo": {"req1": {"url": "/a", "timout": "1s"}, "req2": {"url": "/b"}}});
+        assert!(!validator.is_valid(&config));
+        let config = json!({"todo": {"req": {"url": "/a"}, "res": {"skip_header": ["date"]}}});
+        assert!(!validator.is_valid(&config));
+        let config = json!({"todo": {"req1": {"url": "/a"}, "req2": {"patch": {"path": "/b"}}}});
+        assert!(validator.is_valid(&config));
+        let config = json!({"todo": {"req1": {"url": "/a"}, "req2": {"patch": {"timeout": null}}}});
+        assert!(validator.is_valid(&config));
+
+        let validator = jsonschema::validator_for(&request_config()).unwrap();
+        assert!(!validator.is_valid(&json!({"todo": {"url": "/a", "retires": 1}})));
+        assert!(validator.is_valid(&json!({"todo": {"url": "/a", "extends": "base"}})));
+    }
+}
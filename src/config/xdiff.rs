@@ -1,66 +1,802 @@
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
+use indexmap::IndexMap;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 
-use super::{Load, Validate};
-use crate::{context::ResponseContext, utils::build_diff, Args, RequestContext};
+use super::{
+    lint::lint_file,
+    merge::{apply_defaults, patch_request, request_patch},
+    ConfigFormat, Issues, ItemError, ItemFilter, Load, Problem, Tagged, Validate,
+};
+use crate::{
+    context::{
+        metrics_text, BodySchema, Environment, Noise, PerfContext, ResponseContext,
+        ResponseMetrics, Session,
+    },
+    utils::{build_diff, is_default},
+    DiffArgs, RequestContext,
+};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
 pub struct DiffConfig {
+    /// named environments the items can be run against with `--left` and `--right`
+    #[serde(skip_serializing_if = "IndexMap::is_empty", default)]
+    environments: IndexMap<String, Environment>,
     #[serde(flatten)]
-    items: HashMap<String, DiffItem>,
+    /// items in the order they are written in the file
+    items: IndexMap<String, DiffItem>,
 }
 
 impl DiffConfig {
-    pub fn new(items: HashMap<String, DiffItem>) -> Self {
-        Self { items }
+    pub fn new(items: IndexMap<String, DiffItem>) -> Self {
+        Self {
+            environments: IndexMap::new(),
+            items,
+        }
     }
 
     pub fn get_item(&self, name: &str) -> Option<&DiffItem> {
         self.items.get(name)
     }
+
+    /// the items in the order of the config file
+    pub fn items(&self) -> impl Iterator<Item = (&str, &DiffItem)> {
+        self.items.iter().map(|(name, item)| (name.as_str(), item))
+    }
+
+    /// the items selected by the filter
+    pub fn select_items(&self, filter: &ItemFilter) -> Result<Vec<(&str, &DiffItem)>> {
+        filter.select(&self.items)
+    }
+
+    pub fn get_item_mut(&mut self, name: &str) -> Option<&mut DiffItem> {
+        self.items.get_mut(name)
+    }
+
+    /// add an item at the end, an item of the same name is replaced in place and returned
+    pub fn insert_item(&mut self, name: impl Into<String>, item: DiffItem) -> Option<DiffItem> {
+        self.items.insert(name.into(), item)
+    }
+
+    /// remove an item, the other items keep their order
+    pub fn remove_item(&mut self, name: &str) -> Option<DiffItem> {
+        self.items.shift_remove(name)
+    }
+
+    /// rename an item in place
+    pub fn rename_item(&mut self, from: &str, to: impl Into<String>) -> Result<()> {
+        let to = to.into();
+        if self.items.contains_key(&to) {
+            return Err(anyhow!("item {} already exists", to));
+        }
+        let (idx, _, item) = self
+            .items
+            .shift_remove_full(from)
+            .ok_or_else(|| anyhow!("item {} not found", from))?;
+        self.items.shift_insert(idx, to, item);
+        Ok(())
+    }
+
+    pub fn environment(&self, name: &str) -> Result<&Environment> {
+        self.environments
+            .get(name)
+            .ok_or_else(|| anyhow!("environment {} not found", name))
+    }
+
+    /// json schema of the config, e.g. for editor completion and validation
+    pub fn json_schema() -> serde_json::Value {
+        super::schema::diff_config()
+    }
+
+    /// check every item of a config file without loading it, all problems are reported
+    pub fn lint(path: &str, format: Option<ConfigFormat>) -> Vec<Problem> {
+        lint_file::<Self>(path, format, DiffItem::lint)
+    }
 }
 
-impl Load for DiffConfig {}
+impl Load for DiffConfig {
+    /// apply the top level `defaults` to every request and build `req2: {patch: ...}`
+    /// from req1
+    fn resolve(mut value: serde_yaml::Value) -> Result<serde_yaml::Value> {
+        let Some(map) = value.as_mapping_mut() else {
+            return Ok(value);
+        };
+        let defaults = map.shift_remove("defaults");
+        for (name, item) in map.iter_mut() {
+            if name.as_str() == Some("environments") {
+                continue;
+            }
+            let Some(item) = item.as_mapping_mut() else {
+                continue;
+            };
+            let name = name.as_str().unwrap_or_default();
+            if let Some(defaults) = &defaults {
+                for key in ["req", "req1", "req2"] {
+                    if let Some(req) = item.get_mut(key) {
+                        if request_patch(req).is_none() {
+                            apply_defaults(defaults, req);
+                        }
+                    }
+                }
+                if let Some(serde_yaml::Value::Mapping(requests)) = item.get_mut("requests") {
+                    for req in requests.values_mut() {
+                        apply_defaults(defaults, req);
+                    }
+                }
+            }
+            let patched = match item.get("req2").and_then(request_patch) {
+                Some(patch) => {
+                    let base = item
+                        .get("req1")
+                        .ok_or_else(|| anyhow!("item {}: req2 patch requires req1", name))?;
+                    patch_request(base, patch)
+                        .with_context(|| format!("item {}: failed to patch req2", name))?
+                }
+                None => continue,
+            };
+            item.insert("req2".into(), patched);
+        }
+        Ok(value)
+    }
+}
 
 impl Validate for DiffConfig {
     fn validate(&self) -> Result<()> {
+        for (name, env) in self.environments.iter() {
+            env.validate()
+                .context(format!("failed to validate environment: {}", name))?;
+        }
         for (name, item) in self.items.iter() {
-            item.validate()
-                .context(format!("failed to validate item: {}", name))?;
+            item.validate().context(ItemError(name.clone()))?;
         }
         Ok(())
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
 pub struct DiffItem {
-    req1: RequestContext,
-    req2: RequestContext,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    description: Option<String>,
+    /// tags to select the item with `--tag` and `--exclude-tag`, e.g. smoke or nightly
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    tags: Vec<String>,
+    /// a single request sent to both the left and the right environment
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    req: Option<RequestContext>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    req1: Option<RequestContext>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    req2: Option<RequestContext>,
+    /// named requests for comparing more than two endpoints, used instead of req1/req2
+    #[serde(skip_serializing_if = "IndexMap::is_empty", default)]
+    requests: IndexMap<String, RequestContext>,
+    /// name of the request every other response is diffed against, defaults to the first one
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    baseline: Option<String>,
     #[serde(skip_serializing_if = "is_default", default)]
     res: ResponseContext,
+    #[serde(skip_serializing_if = "is_default", default)]
+    perf: PerfContext,
+}
+
+impl Tagged for DiffItem {
+    fn tags(&self) -> &[String] {
+        DiffItem::tags(self)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DiffResult {
+    /// the rendered diff and metrics of the responses
+    pub output: String,
+    /// reasons why the item failed, empty if it passed
+    pub failures: Vec<String>,
 }
 
-fn is_default<T: Default + PartialEq>(v: &T) -> bool {
-    v == &T::default()
+impl DiffResult {
+    pub fn new(output: String, failures: Vec<String>) -> Self {
+        Self { output, failures }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
 }
 
 impl DiffItem {
-    pub fn new(req1: RequestContext, req2: RequestContext, res: ResponseContext) -> Self {
-        Self { req1, req2, res }
+    /// the requests, the response and the other fields are checked on their own, so a
+    /// problem in one of them doesn't hide the others
+    fn lint(value: &serde_yaml::Value, envs: &IndexMap<String, Environment>) -> Issues {
+        let mut issues = Issues::default();
+        let Some(map) = value.as_mapping() else {
+            issues.deserialize::<DiffItem>(value);
+            return issues;
+        };
+        let mut rest = map.clone();
+        let mut requests = vec![];
+        for key in ["req", "req1", "req2"] {
+            if let Some(req) = rest.shift_remove(key) {
+                requests.push((key.to_string(), req));
+            }
+        }
+        if let Some(serde_yaml::Value::Mapping(map)) = rest.get("requests") {
+            requests.extend(map.iter().map(|(name, req)| {
+                let name = name.as_str().unwrap_or_default();
+                (format!("requests.{}", name), req.clone())
+            }));
+            rest.shift_remove("requests");
+        }
+        for (path, req) in requests {
+            let mut req_issues = Issues::default();
+            if let Some(req) = req_issues.deserialize::<RequestContext>(&req) {
+                if let Err(e) = req.validate() {
+                    req_issues.add_error("", e);
+                }
+                req_issues.extend("", req.lint(envs));
+            }
+            issues.extend(&path, req_issues);
+        }
+        if let Some(res) = rest.shift_remove("res") {
+            let mut res_issues = Issues::default();
+            if let Some(res) = res_issues.deserialize::<ResponseContext>(&res) {
+                if let Err(e) = res.validate() {
+                    res_issues.add_error("", e);
+                }
+                res_issues.extend("", res.lint());
+            }
+            issues.extend("res", res_issues);
+        }
+        issues.deserialize::<DiffItem>(&serde_yaml::Value::Mapping(rest));
+        // how the requests are set is only checked once all the parts are valid
+        if let Ok(item) = serde_yaml::from_value::<DiffItem>(value.clone()) {
+            if let Err(e) = item.validate_layout() {
+                issues.add_error("", e);
+            }
+        }
+        issues
     }
 
-    pub async fn diff(&self, args: Args) -> Result<String> {
-        let res1 = self.req1.send(&args).await?;
-        let res2 = self.req2.send(&args).await?;
-        let text1 = res1.resolve_text(&self.res).await?;
-        let text2 = res2.resolve_text(&self.res).await?;
-        build_diff(text1, text2)
+    pub fn new(
+        req1: RequestContext,
+        req2: RequestContext,
+        res: ResponseContext,
+        perf: PerfContext,
+    ) -> Self {
+        Self {
+            description: None,
+            tags: vec![],
+            req: None,
+            req1: Some(req1),
+            req2: Some(req2),
+            requests: IndexMap::new(),
+            baseline: None,
+            res,
+            perf,
+        }
     }
 
-    fn validate(&self) -> Result<()> {
-        self.req1.validate()?;
-        self.req2.validate()?;
+    pub fn with_requests(
+        requests: IndexMap<String, RequestContext>,
+        baseline: Option<String>,
+        res: ResponseContext,
+        perf: PerfContext,
+    ) -> Self {
+        Self {
+            description: None,
+            tags: vec![],
+            req: None,
+            req1: None,
+            req2: None,
+            requests,
+            baseline,
+            res,
+            perf,
+        }
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// all requests of the item, the baseline always comes first
+    pub fn requests(&self) -> Vec<(&str, &RequestContext)> {
+        if let Some(req) = &self.req {
+            return vec![("req1", req), ("req2", req)];
+        }
+        if self.requests.is_empty() {
+            return [("req1", &self.req1), ("req2", &self.req2)]
+                .into_iter()
+                .filter_map(|(name, req)| req.as_ref().map(|req| (name, req)))
+                .collect();
+        }
+        let baseline = self.baseline_name();
+        let mut requests: Vec<_> = self
+            .requests
+            .iter()
+            .map(|(name, req)| (name.as_str(), req))
+            .collect();
+        if let Some(idx) = requests.iter().position(|(name, _)| *name == baseline) {
+            let base = requests.remove(idx);
+            requests.insert(0, base);
+        }
+        requests
+    }
+
+    /// resolve the requests in the given environments, the left one is used for req1 or the
+    /// baseline and the right one for all the other requests
+    pub fn with_environments(
+        &self,
+        left: Option<&Environment>,
+        right: Option<&Environment>,
+    ) -> Result<Self> {
+        let resolve = |req: &RequestContext, env: Option<&Environment>| match env {
+            Some(env) => req.with_environment(env),
+            None => Ok(req.clone()),
+        };
+        let mut item = self.clone();
+        if let Some(req) = item.req.take() {
+            item.req1 = Some(req.clone());
+            item.req2 = Some(req);
+        }
+        if let Some(req) = &item.req1 {
+            item.req1 = Some(resolve(req, left).context("failed to resolve req1")?);
+        }
+        if let Some(req) = &item.req2 {
+            item.req2 = Some(resolve(req, right).context("failed to resolve req2")?);
+        }
+        let baseline = self.baseline_name().to_string();
+        for (name, req) in item.requests.iter_mut() {
+            let env = if *name == baseline { left } else { right };
+            *req = resolve(req, env).context(format!("failed to resolve request: {}", name))?;
+        }
+        Ok(item)
+    }
+
+    fn baseline_name(&self) -> &str {
+        match (&self.baseline, self.requests.keys().next()) {
+            (Some(name), _) => name,
+            (None, Some(name)) => name,
+            (None, None) => "req1",
+        }
+    }
+
+    /// send all requests and diff the responses, cookies are kept per request name in `session`
+    /// the left args are applied to the baseline request, the right ones to the others
+    pub async fn diff(&self, args: &DiffArgs, session: &Session) -> Result<DiffResult> {
+        let requests = self.requests();
+        let side_args = |idx: usize| if idx == 0 { &args.left } else { &args.right };
+        let schema = self.res.schema().map(BodySchema::load).transpose()?;
+
+        let mut responses = Vec::with_capacity(requests.len());
+        let mut samples = Vec::with_capacity(requests.len());
+        for (idx, (name, req)) in requests.iter().enumerate() {
+            let res = req
+                .send_with_cookies(side_args(idx), session.cookie_jar(name))
+                .await?;
+            samples.push(vec![res.metrics()]);
+            responses.push(res);
+        }
+        for _ in 1..self.perf.repeat() {
+            for (idx, (name, req)) in requests.iter().enumerate() {
+                let res = req
+                    .send_with_cookies(side_args(idx), session.cookie_jar(name))
+                    .await?;
+                samples[idx].push(res.metrics());
+            }
+        }
+        let metrics: Vec<_> = requests
+            .iter()
+            .zip(samples.iter())
+            .map(|((name, _), samples)| (*name, ResponseMetrics::average(samples)))
+            .collect();
+
+        let mut failures = vec![];
+        for ((name, _), res) in requests.iter().zip(responses.iter()) {
+            let mut violations = res.check(self.res.assertions());
+            if let Some(schema) = &schema {
+                violations.extend(res.check_schema(schema));
+            }
+            failures.extend(violations.into_iter().map(|v| format!("{}: {}", name, v)));
+        }
+
+        let noise = if self.res.detect_noise() {
+            let mut noise = Noise::default();
+            for (idx, ((name, req), res)) in requests.iter().zip(responses.iter()).enumerate() {
+                let secondary = req
+                    .send_with_cookies(side_args(idx), session.cookie_jar(name))
+                    .await?;
+                noise.merge(Noise::detect(res, &secondary));
+            }
+            Some(noise)
+        } else {
+            None
+        };
+        let ctx = match &noise {
+            Some(noise) => noise.apply(&self.res),
+            None => self.res.clone(),
+        };
+
+        let mut texts = Vec::with_capacity(responses.len());
+        for res in responses {
+            texts.push(res.resolve_text(&ctx).await?);
+        }
+
+        let (base_name, base_text) = (requests[0].0, &texts[0]);
+        let others: Vec<_> = requests[1..]
+            .iter()
+            .zip(texts[1..].iter())
+            .map(|((name, _), text)| (*name, text.as_str()))
+            .collect();
+        let groups = group_responses(base_text, &others);
+
+        let mut output = String::new();
+        if let Some(noise) = noise.filter(|n| !n.is_empty()) {
+            output.push_str(&format!("{}\n", noise.summary()));
+        }
+        output.push_str(&format!("{}\n", groups.summary(base_name)));
+        for (names, text) in &groups.differs {
+            output.push_str(&format!("\n{} vs {}:\n", base_name, names.join(", ")));
+            output.push_str(&build_diff(base_text.clone(), text.to_string())?);
+        }
+        output.push('\n');
+        output.push_str(&metrics_text(&metrics));
+
+        for (name, m) in &metrics[1..] {
+            failures.extend(self.perf.check(name, &metrics[0].1, m));
+        }
+        Ok(DiffResult::new(output, failures))
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        self.validate_layout()?;
+        self.res.validate()?;
+        for (name, req) in self.requests() {
+            req.validate()
+                .context(format!("failed to validate request: {}", name))?;
+        }
         Ok(())
     }
+
+    /// the item has one way of setting its requests and the baseline is one of them
+    fn validate_layout(&self) -> Result<()> {
+        if self.req.is_some() {
+            if self.req1.is_some() || self.req2.is_some() || !self.requests.is_empty() {
+                return Err(anyhow!(
+                    "req cannot be used together with req1/req2 or requests"
+                ));
+            }
+        } else if self.requests.is_empty() {
+            if self.req1.is_none() || self.req2.is_none() {
+                return Err(anyhow!("either req1 and req2 or requests must be set"));
+            }
+        } else {
+            if self.req1.is_some() || self.req2.is_some() {
+                return Err(anyhow!("req1/req2 cannot be used together with requests"));
+            }
+            if self.requests.len() < 2 {
+                return Err(anyhow!("requests must contain at least two requests"));
+            }
+            if let Some(baseline) = &self.baseline {
+                if !self.requests.contains_key(baseline) {
+                    return Err(anyhow!("baseline {} not found in requests", baseline));
+                }
+            }
+        }
+        if self.requests.is_empty() && self.baseline.is_some() {
+            return Err(anyhow!("baseline can only be used with requests"));
+        }
+        Ok(())
+    }
+}
+
+/// responses grouped by how they compare to the baseline
+#[derive(Debug, Default, PartialEq, Eq)]
+struct ResponseGroups<'a> {
+    /// names of the responses identical to the baseline
+    matches: Vec<&'a str>,
+    /// responses different from the baseline, identical ones share a group
+    differs: Vec<(Vec<&'a str>, &'a str)>,
+}
+
+impl ResponseGroups<'_> {
+    fn summary(&self, base_name: &str) -> String {
+        let mut parts = vec![];
+        if !self.matches.is_empty() {
+            let verb = if self.matches.len() == 1 {
+                "matches"
+            } else {
+                "match"
+            };
+            parts.push(format!(
+                "{} {} baseline {}",
+                self.matches.join(", "),
+                verb,
+                base_name
+            ));
+        }
+        for (names, _) in &self.differs {
+            let verb = if names.len() == 1 {
+                "differs"
+            } else {
+                "differ"
+            };
+            parts.push(format!("{} {}", names.join(", "), verb));
+        }
+        parts.join("; ")
+    }
+}
+
+fn group_responses<'a>(base: &str, others: &[(&'a str, &'a str)]) -> ResponseGroups<'a> {
+    let mut groups = ResponseGroups::default();
+    for (name, text) in others {
+        if *text == base {
+            groups.matches.push(name);
+        } else if let Some((names, _)) = groups.differs.iter_mut().find(|(_, t)| t == text) {
+            names.push(name);
+        } else {
+            groups.differs.push((vec![name], text));
+        }
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        context::mock::{mock_server, response},
+        Args,
+    };
+
+    #[test]
+    fn group_responses_should_work() {
+        let others = [
+            ("eu", "a"),
+            ("ap", "b"),
+            ("us", "a"),
+            ("sa", "b"),
+            ("af", "c"),
+        ];
+        let groups = group_responses("a", &others);
+        assert_eq!(groups.matches, vec!["eu", "us"]);
+        assert_eq!(
+            groups.differs,
+            vec![(vec!["ap", "sa"], "b"), (vec!["af"], "c")]
+        );
+        assert_eq!(
+            groups.summary("base"),
+            "eu, us match baseline base; ap, sa differ; af differs"
+        );
+    }
+
+    #[test]
+    fn items_should_keep_file_order() {
+        let config = DiffConfig::from_yaml(
+            r#"
+zeta:
+  req:
+    url: https://z.example.com/
+alpha:
+  req:
+    url: https://a.example.com/
+"#,
+        )
+        .unwrap();
+        let mut config = config;
+        let names = |config: &DiffConfig| -> Vec<String> {
+            config.items().map(|(name, _)| name.to_string()).collect()
+        };
+        assert_eq!(names(&config), vec!["zeta", "alpha"]);
+
+        let item = config.get_item("alpha").unwrap().clone();
+        assert!(config.insert_item("beta", item.clone()).is_none());
+        assert!(config.insert_item("zeta", item).is_some());
+        config.rename_item("zeta", "omega").unwrap();
+        assert_eq!(names(&config), vec!["omega", "alpha", "beta"]);
+        assert!(config.rename_item("omega", "beta").is_err());
+        assert!(config.rename_item("zeta", "x").is_err());
+        assert!(config.remove_item("alpha").is_some());
+        assert_eq!(names(&config), vec!["omega", "beta"]);
+
+        let config = DiffConfig::from_toml(
+            "[zeta.req]\nurl = \"https://z.example.com/\"\n\n\
+             [alpha.req]\nurl = \"https://a.example.com/\"\n",
+        )
+        .unwrap();
+        assert_eq!(names(&config), vec!["zeta", "alpha"]);
+    }
+
+    #[tokio::test]
+    async fn select_items_should_filter_by_tags() {
+        let config = DiffConfig::load_yaml("fixtures/test.yaml").await.unwrap();
+        let rust = config.get_item("rust").unwrap();
+        assert_eq!(rust.description(), Some("the rust home page"));
+        assert_eq!(rust.tags(), ["smoke"]);
+
+        let filter = ItemFilter::new(&[], &["smoke".into()], &["full".into()]).unwrap();
+        let items = config.select_items(&filter).unwrap();
+        let names: Vec<_> = items.iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, vec!["rust"]);
+
+        let filter = ItemFilter::new(&["todo*".into()], &[], &[]).unwrap();
+        let items = config.select_items(&filter).unwrap();
+        let names: Vec<_> = items.iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, vec!["todo", "todos", "todo_patch"]);
+    }
+
+    #[test]
+    fn requests_should_put_baseline_first() {
+        let config = DiffConfig::from_yaml(
+            r#"
+regions:
+  baseline: us
+  requests:
+    eu:
+      url: https://eu.example.com/
+    us:
+      url: https://us.example.com/
+    ap:
+      url: https://ap.example.com/
+"#,
+        )
+        .unwrap();
+        let item = config.get_item("regions").unwrap();
+        let names: Vec<_> = item.requests().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["us", "eu", "ap"]);
+    }
+
+    #[test]
+    fn req2_patch_should_inherit_req1_and_defaults() {
+        let config = DiffConfig::from_yaml(
+            r#"
+defaults:
+  headers:
+    user-agent: xdiff
+  params:
+    lang: en
+todo:
+  req1:
+    url: https://a.example.com/todos/1
+    params:
+      a: 1
+  req2:
+    patch:
+      host: b.example.com
+      params:
+        a: ~
+        b: 2
+"#,
+        )
+        .unwrap();
+        let item = config.get_item("todo").unwrap();
+        let args = Args::default();
+        let reqs = item.requests();
+        assert_eq!(
+            reqs[0].1.url(&args).unwrap(),
+            "https://a.example.com/todos/1?a=1&lang=en"
+        );
+        assert_eq!(
+            reqs[1].1.url(&args).unwrap(),
+            "https://b.example.com/todos/1?b=2&lang=en"
+        );
+        let (headers, _, _) = reqs[1].1.generate(&args).unwrap();
+        assert_eq!(headers.get("user-agent").unwrap(), "xdiff");
+        assert!(config.get_item("defaults").is_none());
+    }
+
+    #[test]
+    fn validate_should_reject_unknown_baseline() {
+        let result = DiffConfig::from_yaml(
+            r#"
+regions:
+  baseline: ap
+  requests:
+    eu:
+      url: https://eu.example.com/
+    us:
+      url: https://us.example.com/
+"#,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn with_environments_should_resolve_both_sides() {
+        let config = DiffConfig::from_yaml(
+            r#"
+environments:
+  staging:
+    base_url: https://staging.example.com/api
+    headers:
+      x-env: staging
+    variables:
+      id: "1"
+  prod:
+    base_url: https://example.com/api
+    variables:
+      id: "2"
+todo:
+  req:
+    url: /todos/{{id}}
+"#,
+        )
+        .unwrap();
+        let item = config.get_item("todo").unwrap();
+        let left = config.environment("staging").unwrap();
+        let right = config.environment("prod").unwrap();
+        let item = item.with_environments(Some(left), Some(right)).unwrap();
+        let args = Args::default();
+        let urls: Vec<_> = item
+            .requests()
+            .into_iter()
+            .map(|(name, req)| (name, req.url(&args).unwrap()))
+            .collect();
+        assert_eq!(
+            urls,
+            vec![
+                (
+                    "req1",
+                    "https://staging.example.com/api/todos/1".to_string()
+                ),
+                ("req2", "https://example.com/api/todos/2".to_string()),
+            ]
+        );
+        let (headers, _, _) = item.requests()[0].1.generate(&args).unwrap();
+        assert_eq!(headers.get("x-env").unwrap(), "staging");
+        assert!(config.environment("dev").is_err());
+    }
+
+    #[tokio::test]
+    async fn load_yaml_should_resolve_includes_and_extends() {
+        let config = DiffConfig::load_yaml("fixtures/split.yaml").await.unwrap();
+        // included items come before the ones of the including file
+        let names: Vec<_> = config.items().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["user", "todo_split"]);
+        assert!(config.environment("staging").is_ok());
+        let user = config.get_item("user").unwrap();
+        assert_eq!(
+            user.res,
+            ResponseContext::new(
+                vec![
+                    "report-to".into(),
+                    "date".into(),
+                    "cf-ray".into(),
+                    "age".into()
+                ],
+                vec!["id".into()]
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn diff_should_ignore_detected_noise() {
+        let json = [("content-type", "application/json")];
+        let (url1, _) = mock_server(vec![
+            response("200 OK", &json, r#"{"id":1,"ts":1}"#),
+            response("200 OK", &json, r#"{"id":1,"ts":2}"#),
+        ])
+        .await;
+        let (url2, _) = mock_server(vec![
+            response("200 OK", &json, r#"{"id":1,"ts":3}"#),
+            response("200 OK", &json, r#"{"id":1,"ts":4}"#),
+        ])
+        .await;
+        let config = DiffConfig::from_yaml(&format!(
+            "todo:\n  req1:\n    url: {}/\n  req2:\n    url: {}/\n  res:\n    skip_headers: [date]\n    detect_noise: true\n",
+            url1, url2
+        ))
+        .unwrap();
+        let item = config.get_item("todo").unwrap();
+        let result = item
+            .diff(&DiffArgs::default(), &Session::new())
+            .await
+            .unwrap();
+        let mut lines = result.output.lines();
+        assert_eq!(lines.next(), Some("noise: body ts"));
+        assert_eq!(lines.next(), Some("req2 matches baseline req1"));
+    }
 }
@@ -1,33 +1,235 @@
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
+use indexmap::IndexMap;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 
-use super::{Load, Validate};
-use crate::RequestContext;
+use super::{
+    lint::lint_file, merge::apply_defaults, ConfigFormat, Issues, ItemError, ItemFilter, Load,
+    Problem, Tagged, Validate,
+};
+use crate::{
+    context::{load_schema, Assertions, Environment},
+    utils::is_default,
+    RequestContext,
+};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
 pub struct RequestConfig {
+    /// named environments the items can be run against with `--env`
+    #[serde(skip_serializing_if = "IndexMap::is_empty", default)]
+    environments: IndexMap<String, Environment>,
     #[serde(flatten)]
-    items: HashMap<String, RequestContext>,
+    /// items in the order they are written in the file
+    items: IndexMap<String, RequestItem>,
 }
 
-impl Load for RequestConfig {}
+impl Load for RequestConfig {
+    /// apply the top level `defaults` to every item
+    fn resolve(mut value: serde_yaml::Value) -> Result<serde_yaml::Value> {
+        let Some(map) = value.as_mapping_mut() else {
+            return Ok(value);
+        };
+        if let Some(defaults) = map.shift_remove("defaults") {
+            for (name, item) in map.iter_mut() {
+                if name.as_str() != Some("environments") && item.is_mapping() {
+                    apply_defaults(&defaults, item);
+                }
+            }
+        }
+        Ok(value)
+    }
+}
 
 impl RequestConfig {
-    pub fn new(items: HashMap<String, RequestContext>) -> Self {
-        Self { items }
+    pub fn new(items: IndexMap<String, RequestItem>) -> Self {
+        Self {
+            environments: IndexMap::new(),
+            items,
+        }
     }
 
-    pub fn get_item(&self, name: &str) -> Option<&RequestContext> {
+    pub fn get_item(&self, name: &str) -> Option<&RequestItem> {
         self.items.get(name)
     }
+
+    /// the items in the order of the config file
+    pub fn items(&self) -> impl Iterator<Item = (&str, &RequestItem)> {
+        self.items.iter().map(|(name, item)| (name.as_str(), item))
+    }
+
+    /// the items selected by the filter
+    pub fn select_items(&self, filter: &ItemFilter) -> Result<Vec<(&str, &RequestItem)>> {
+        filter.select(&self.items)
+    }
+
+    pub fn get_item_mut(&mut self, name: &str) -> Option<&mut RequestItem> {
+        self.items.get_mut(name)
+    }
+
+    /// add an item at the end, an item of the same name is replaced in place and returned
+    pub fn insert_item(
+        &mut self,
+        name: impl Into<String>,
+        item: RequestItem,
+    ) -> Option<RequestItem> {
+        self.items.insert(name.into(), item)
+    }
+
+    /// remove an item, the other items keep their order
+    pub fn remove_item(&mut self, name: &str) -> Option<RequestItem> {
+        self.items.shift_remove(name)
+    }
+
+    /// rename an item in place
+    pub fn rename_item(&mut self, from: &str, to: impl Into<String>) -> Result<()> {
+        let to = to.into();
+        if self.items.contains_key(&to) {
+            return Err(anyhow!("item {} already exists", to));
+        }
+        let (idx, _, item) = self
+            .items
+            .shift_remove_full(from)
+            .ok_or_else(|| anyhow!("item {} not found", from))?;
+        self.items.shift_insert(idx, to, item);
+        Ok(())
+    }
+
+    pub fn environment(&self, name: &str) -> Result<&Environment> {
+        self.environments
+            .get(name)
+            .ok_or_else(|| anyhow!("environment {} not found", name))
+    }
+
+    /// json schema of the config, e.g. for editor completion and validation
+    pub fn json_schema() -> serde_json::Value {
+        super::schema::request_config()
+    }
+
+    /// check every item of a config file without loading it, all problems are reported
+    pub fn lint(path: &str, format: Option<ConfigFormat>) -> Vec<Problem> {
+        lint_file::<Self>(path, format, RequestItem::lint)
+    }
 }
 
 impl Validate for RequestConfig {
     fn validate(&self) -> Result<()> {
+        for (name, env) in self.environments.iter() {
+            env.validate()
+                .context(format!("failed to validate environment: {}", name))?;
+        }
         for (name, item) in self.items.iter() {
-            item.validate()
-                .context(format!("failed to validate item: {}", name))?;
+            item.validate().context(ItemError(name.clone()))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct RequestItem {
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    description: Option<String>,
+    /// tags to select the item with `--tag` and `--exclude-tag`, e.g. smoke or nightly
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    tags: Vec<String>,
+    #[serde(flatten)]
+    req: RequestContext,
+    #[serde(rename = "assert", skip_serializing_if = "is_default", default)]
+    assertions: Assertions,
+    /// path of a local json schema file the body must conform to
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    schema: Option<String>,
+}
+
+impl Tagged for RequestItem {
+    fn tags(&self) -> &[String] {
+        RequestItem::tags(self)
+    }
+}
+
+impl RequestItem {
+    pub fn new(req: RequestContext, assertions: Assertions, schema: Option<String>) -> Self {
+        Self {
+            description: None,
+            tags: vec![],
+            req,
+            assertions,
+            schema,
+        }
+    }
+
+    /// the item with its request resolved in the environment
+    pub fn with_environment(&self, env: &Environment) -> Result<Self> {
+        let mut item = self.clone();
+        item.req = self.req.with_environment(env)?;
+        Ok(item)
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    pub fn request(&self) -> &RequestContext {
+        &self.req
+    }
+
+    pub fn assertions(&self) -> &Assertions {
+        &self.assertions
+    }
+
+    pub fn schema(&self) -> Option<&str> {
+        self.schema.as_deref()
+    }
+
+    /// the request is flattened into the item, so its parts are checked one by one
+    /// to find the unknown fields
+    fn lint(value: &serde_yaml::Value, envs: &IndexMap<String, Environment>) -> Issues {
+        let mut issues = Issues::default();
+        let mut req = value.clone();
+        let mut take = |key: &str| req.as_mapping_mut().and_then(|map| map.remove(key));
+        let (assertions, schema) = (take("assert"), take("schema"));
+        let (tags, description) = (take("tags"), take("description"));
+        if let Some(req) = issues.deserialize::<RequestContext>(&req) {
+            if let Err(e) = req.validate() {
+                issues.add_error("", e);
+            }
+            issues.extend("", req.lint(envs));
+        }
+        if let Some(assertions) = assertions {
+            let mut assert_issues = Issues::default();
+            if let Some(assertions) = assert_issues.deserialize::<Assertions>(&assertions) {
+                if let Err(e) = assertions.validate() {
+                    assert_issues.add_error("", e);
+                }
+            }
+            issues.extend("assert", assert_issues);
+        }
+        if tags.is_some_and(|tags| serde_yaml::from_value::<Vec<String>>(tags).is_err()) {
+            issues.add("tags", "tags must be a list of strings");
+        }
+        if description.is_some_and(|d| !d.is_string()) {
+            issues.add("description", "description must be a string");
+        }
+        match schema.as_ref().map(|s| s.as_str()) {
+            Some(Some(path)) => {
+                if let Err(e) = load_schema(path) {
+                    issues.add_error("schema", e);
+                }
+            }
+            Some(None) => issues.add("schema", "schema must be a path"),
+            None => {}
+        }
+        issues
+    }
+
+    fn validate(&self) -> Result<()> {
+        self.req.validate()?;
+        self.assertions.validate()?;
+        if let Some(schema) = &self.schema {
+            load_schema(schema)?;
         }
         Ok(())
     }
@@ -0,0 +1,320 @@
+use std::{fmt, sync::OnceLock};
+
+use anyhow::{anyhow, Result};
+use http::{HeaderMap, StatusCode};
+use indexmap::IndexMap;
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::schema::schema_violations;
+use crate::utils::get_json_path;
+
+/// invariants checked on every response after it is received
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Default, PartialEq, Eq)]
+pub struct Assertions {
+    /// the status code must be one of these
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    status: Vec<u16>,
+    /// header name to the regex its value must match, `~` only requires the header to be present
+    #[serde(skip_serializing_if = "IndexMap::is_empty", default)]
+    headers: IndexMap<String, Option<Pattern>>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    json: Vec<JsonAssertion>,
+    /// max size of the body in bytes
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    max_body_size: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Default, PartialEq, Eq)]
+pub struct JsonAssertion {
+    /// dot separated path into the json body, e.g. `data.items.0.id`
+    path: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    equals: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    exists: Option<bool>,
+    /// regex the value must match, non-string values are matched against their json text
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    matches: Option<Pattern>,
+    /// inline json schema the value must conform to
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    schema: Option<Value>,
+}
+
+impl Assertions {
+    pub fn validate(&self) -> Result<()> {
+        for status in &self.status {
+            StatusCode::from_u16(*status)
+                .map_err(|_| anyhow!("assert: invalid status code {}", status))?;
+        }
+        for (name, pattern) in &self.headers {
+            if let Some(pattern) = pattern {
+                pattern
+                    .regex()
+                    .map_err(|e| anyhow!("assert: invalid regex for header {}: {}", name, e))?;
+            }
+        }
+        for json in &self.json {
+            if let Some(pattern) = &json.matches {
+                pattern
+                    .regex()
+                    .map_err(|e| anyhow!("assert: invalid regex for {}: {}", json.path, e))?;
+            }
+            if let Some(schema) = &json.schema {
+                jsonschema::validator_for(schema)
+                    .map_err(|e| anyhow!("assert: invalid schema for {}: {}", json.path, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// check the response against the assertions, return the violated ones
+    pub fn check(&self, status: StatusCode, headers: &HeaderMap, body: &[u8]) -> Vec<String> {
+        let mut failures = vec![];
+
+        if !self.status.is_empty() && !self.status.contains(&status.as_u16()) {
+            failures.push(format!(
+                "status {} is not one of {:?}",
+                status.as_u16(),
+                self.status
+            ));
+        }
+
+        for (name, pattern) in &self.headers {
+            let value = match headers.get(name.as_str()) {
+                Some(v) => String::from_utf8_lossy(v.as_bytes()).to_string(),
+                None => {
+                    failures.push(format!("header {} is missing", name));
+                    continue;
+                }
+            };
+            if let Some(pattern) = pattern {
+                if !pattern.is_match(&value) {
+                    failures.push(format!(
+                        "header {}: {:?} does not match /{}/",
+                        name, value, pattern
+                    ));
+                }
+            }
+        }
+
+        if !self.json.is_empty() {
+            match serde_json::from_slice::<Value>(body) {
+                Ok(json) => {
+                    for assertion in &self.json {
+                        failures.extend(assertion.check(&json));
+                    }
+                }
+                Err(e) => failures.push(format!("body is not valid json: {}", e)),
+            }
+        }
+
+        if let Some(max) = self.max_body_size {
+            if body.len() > max {
+                failures.push(format!("body size {}B exceeds {}B", body.len(), max));
+            }
+        }
+
+        failures
+    }
+}
+
+impl JsonAssertion {
+    fn check(&self, json: &Value) -> Vec<String> {
+        let mut failures = vec![];
+        let value = get_json_path(json, &self.path);
+
+        match (self.exists, value) {
+            (Some(true), None) => failures.push(format!("json {} does not exist", self.path)),
+            (Some(false), Some(_)) => failures.push(format!("json {} should not exist", self.path)),
+            _ => {}
+        }
+
+        if let Some(expected) = &self.equals {
+            if value != Some(expected) {
+                failures.push(format!(
+                    "json {} is {}, expected {}",
+                    self.path,
+                    value.map_or_else(|| "missing".to_string(), |v| v.to_string()),
+                    expected
+                ));
+            }
+        }
+
+        if let Some(pattern) = &self.matches {
+            let text = match value {
+                Some(Value::String(s)) => Some(s.clone()),
+                Some(v) => Some(v.to_string()),
+                None => None,
+            };
+            if !text.as_ref().is_some_and(|text| pattern.is_match(text)) {
+                failures.push(format!(
+                    "json {}: {} does not match /{}/",
+                    self.path,
+                    text.unwrap_or_else(|| "missing".to_string()),
+                    pattern
+                ));
+            }
+        }
+
+        if let Some(schema) = &self.schema {
+            match value.map(|v| schema_violations(schema, v)) {
+                Some(Ok(violations)) => failures.extend(
+                    violations
+                        .into_iter()
+                        .map(|v| format!("json {}: {}", self.path, v)),
+                ),
+                Some(Err(e)) => failures.push(format!("json {}: {}", self.path, e)),
+                None => failures.push(format!("json {} does not exist", self.path)),
+            }
+        }
+
+        failures
+    }
+}
+
+/// a regex written as text in the config, compiled once when it is first used
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Default)]
+#[serde(transparent)]
+pub(crate) struct Pattern {
+    text: String,
+    #[serde(skip)]
+    #[schemars(skip)]
+    regex: OnceLock<Regex>,
+}
+
+impl Pattern {
+    /// the compiled regex, `validate` compiles it before any response is checked
+    fn regex(&self) -> Result<&Regex, regex::Error> {
+        if let Some(re) = self.regex.get() {
+            return Ok(re);
+        }
+        let re = Regex::new(&self.text)?;
+        Ok(self.regex.get_or_init(|| re))
+    }
+
+    fn is_match(&self, text: &str) -> bool {
+        self.regex().is_ok_and(|re| re.is_match(text))
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.text == other.text
+    }
+}
+
+impl Eq for Pattern {}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::{header, HeaderValue};
+    use serde_json::json;
+
+    use super::*;
+
+    fn assertions(yaml: &str) -> Assertions {
+        let assertions: Assertions = serde_yaml::from_str(yaml).unwrap();
+        assertions.validate().unwrap();
+        assertions
+    }
+
+    #[test]
+    fn check_should_pass() {
+        let assertions = assertions(
+            r#"
+status: [200, 201]
+headers:
+  content-type: ^application/json
+  x-request-id: ~
+json:
+  - path: data.items.0.id
+    equals: 1
+  - path: data.name
+    matches: ^al
+  - path: data.deleted
+    exists: false
+  - path: data.items
+    schema:
+      type: array
+      items:
+        required: [id]
+max_body_size: 1024
+"#,
+        );
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json; charset=utf-8"),
+        );
+        headers.insert("x-request-id", HeaderValue::from_static("abc"));
+        let body = json!({"data": {"items": [{"id": 1}], "name": "alice"}}).to_string();
+
+        let failures = assertions.check(StatusCode::OK, &headers, body.as_bytes());
+        assert!(failures.is_empty(), "{:?}", failures);
+    }
+
+    #[test]
+    fn check_should_report_failures() {
+        let assertions = assertions(
+            r#"
+status: [200]
+headers:
+  x-request-id: ~
+json:
+  - path: data.id
+    equals: 1
+  - path: data.missing
+    exists: true
+max_body_size: 4
+"#,
+        );
+        let body = json!({"data": {"id": 2}}).to_string();
+        let failures = assertions.check(StatusCode::NOT_FOUND, &HeaderMap::new(), body.as_bytes());
+        assert_eq!(
+            failures,
+            vec![
+                "status 404 is not one of [200]".to_string(),
+                "header x-request-id is missing".to_string(),
+                "json data.id is 2, expected 1".to_string(),
+                "json data.missing does not exist".to_string(),
+                format!("body size {}B exceeds 4B", body.len()),
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_should_reject_invalid_regex() {
+        let assertions: Assertions = serde_yaml::from_str("headers:\n  x-foo: '(['\n").unwrap();
+        assert!(assertions.validate().is_err());
+    }
+
+    #[test]
+    fn validate_should_compile_patterns_once() {
+        let assertions =
+            assertions("headers:\n  x-foo: ^a\njson:\n  - path: id\n    matches: b$\n");
+        assert!(assertions.headers["x-foo"]
+            .as_ref()
+            .unwrap()
+            .regex
+            .get()
+            .is_some());
+        assert!(assertions.json[0]
+            .matches
+            .as_ref()
+            .unwrap()
+            .regex
+            .get()
+            .is_some());
+        let copy = assertions.clone();
+        assert_eq!(copy, assertions);
+    }
+}
@@ -0,0 +1,245 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use http::{header, HeaderMap, HeaderValue};
+use reqwest::Client;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// tokens are refreshed this long before they actually expire
+const EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+
+/// a secret given inline, read from an environment variable or read from a file
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Secret {
+    Value(String),
+    Env { env: String },
+    File { file: String },
+}
+
+impl Secret {
+    pub fn resolve(&self) -> Result<String> {
+        match self {
+            Secret::Value(v) => Ok(v.clone()),
+            Secret::Env { env } => std::env::var(env)
+                .with_context(|| format!("environment variable {} is not set", env)),
+            Secret::File { file } => std::fs::read_to_string(file)
+                .map(|v| v.trim().to_string())
+                .with_context(|| format!("failed to read secret file: {}", file)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuthContext {
+    Basic {
+        username: String,
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        password: Option<Secret>,
+    },
+    Bearer {
+        token: Secret,
+    },
+    Oauth2(OAuth2Context),
+}
+
+/// OAuth2 client-credentials grant, tokens are cached until they expire
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+pub struct OAuth2Context {
+    token_url: Url,
+    client_id: String,
+    client_secret: Secret,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    audience: Option<String>,
+}
+
+impl AuthContext {
+    /// set the authorization header, an explicitly configured one takes precedence
+    pub async fn apply(&self, headers: &mut HeaderMap) -> Result<()> {
+        if headers.contains_key(header::AUTHORIZATION) {
+            return Ok(());
+        }
+        let value = match self {
+            AuthContext::Basic { username, password } => {
+                let password = password.as_ref().map(Secret::resolve).transpose()?;
+                let credentials = format!("{}:{}", username, password.unwrap_or_default());
+                format!("Basic {}", STANDARD.encode(credentials))
+            }
+            AuthContext::Bearer { token } => format!("Bearer {}", token.resolve()?),
+            AuthContext::Oauth2(oauth2) => format!("Bearer {}", oauth2.token().await?),
+        };
+        let mut value = HeaderValue::from_str(&value)?;
+        value.set_sensitive(true);
+        headers.insert(header::AUTHORIZATION, value);
+        Ok(())
+    }
+}
+
+impl OAuth2Context {
+    async fn token(&self) -> Result<String> {
+        let key = format!(
+            "{}|{}|{}|{}",
+            self.token_url,
+            self.client_id,
+            self.scope.as_deref().unwrap_or_default(),
+            self.audience.as_deref().unwrap_or_default()
+        );
+        if let Some(token) = cached_token(&key) {
+            return Ok(token);
+        }
+
+        let token_url = &self.token_url;
+        let secret = self.client_secret.resolve()?;
+        let mut form = vec![
+            ("grant_type", "client_credentials"),
+            ("client_id", &self.client_id),
+            ("client_secret", &secret),
+        ];
+        if let Some(scope) = &self.scope {
+            form.push(("scope", scope));
+        }
+        if let Some(audience) = &self.audience {
+            form.push(("audience", audience));
+        }
+        let res = Client::new()
+            .post(token_url.clone())
+            .form(&form)
+            .send()
+            .await
+            .with_context(|| format!("failed to fetch oauth2 token from {}", token_url))?;
+        if !res.status().is_success() {
+            return Err(anyhow!(
+                "failed to fetch oauth2 token from {}: {}",
+                token_url,
+                res.status()
+            ));
+        }
+        let token: TokenResponse = res.json().await?;
+        let expires_at = token
+            .expires_in
+            .map(|secs| Instant::now() + Duration::from_secs(secs));
+        token_cache()
+            .lock()
+            .unwrap()
+            .insert(key, (token.access_token.clone(), expires_at));
+        Ok(token.access_token)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(default)]
+    expires_in: Option<u64>,
+}
+
+type TokenCache = Mutex<HashMap<String, (String, Option<Instant>)>>;
+
+fn token_cache() -> &'static TokenCache {
+    static CACHE: OnceLock<TokenCache> = OnceLock::new();
+    CACHE.get_or_init(Default::default)
+}
+
+fn cached_token(key: &str) -> Option<String> {
+    let cache = token_cache().lock().unwrap();
+    match cache.get(key) {
+        Some((token, None)) => Some(token.clone()),
+        Some((token, Some(expires_at))) if Instant::now() + EXPIRY_MARGIN < *expires_at => {
+            Some(token.clone())
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+    use crate::context::mock::{mock_server, response};
+
+    /// a token endpoint that hands out `token-1`, `token-2`, ... on every request
+    async fn mock_token_server(expires_in: u64) -> (Url, Arc<AtomicUsize>) {
+        let responses = (1..=3)
+            .map(|i| {
+                let body = format!(
+                    r#"{{"access_token":"token-{}","token_type":"bearer","expires_in":{}}}"#,
+                    i, expires_in
+                );
+                response("200 OK", &[("content-type", "application/json")], &body)
+            })
+            .collect();
+        let (url, hits) = mock_server(responses).await;
+        (format!("{}/token", url).parse().unwrap(), hits)
+    }
+
+    fn oauth2(token_url: Url) -> AuthContext {
+        AuthContext::Oauth2(OAuth2Context {
+            token_url,
+            client_id: "xdiff".to_string(),
+            client_secret: Secret::Value("secret".to_string()),
+            scope: Some("read".to_string()),
+            audience: None,
+        })
+    }
+
+    async fn authorization(auth: &AuthContext) -> String {
+        let mut headers = HeaderMap::new();
+        auth.apply(&mut headers).await.unwrap();
+        headers[header::AUTHORIZATION].to_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn basic_and_bearer_should_work() {
+        let auth: AuthContext =
+            serde_yaml::from_str("type: basic\nusername: alice\npassword: wonderland\n").unwrap();
+        assert_eq!(authorization(&auth).await, "Basic YWxpY2U6d29uZGVybGFuZA==");
+
+        std::env::set_var("XDIFF_TEST_BEARER_TOKEN", "abc");
+        let auth: AuthContext =
+            serde_yaml::from_str("type: bearer\ntoken:\n  env: XDIFF_TEST_BEARER_TOKEN\n").unwrap();
+        assert_eq!(authorization(&auth).await, "Bearer abc");
+    }
+
+    #[tokio::test]
+    async fn explicit_authorization_header_should_win() {
+        let auth = AuthContext::Bearer {
+            token: Secret::Value("abc".to_string()),
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("Token xyz"));
+        auth.apply(&mut headers).await.unwrap();
+        assert_eq!(headers[header::AUTHORIZATION], "Token xyz");
+    }
+
+    #[tokio::test]
+    async fn oauth2_token_should_be_cached() {
+        let (url, hits) = mock_token_server(3600).await;
+        let auth = oauth2(url);
+        assert_eq!(authorization(&auth).await, "Bearer token-1");
+        assert_eq!(authorization(&auth).await, "Bearer token-1");
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn oauth2_token_should_be_refreshed_when_expired() {
+        let (url, hits) = mock_token_server(0).await;
+        let auth = oauth2(url);
+        assert_eq!(authorization(&auth).await, "Bearer token-1");
+        assert_eq!(authorization(&auth).await, "Bearer token-2");
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+}
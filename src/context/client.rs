@@ -0,0 +1,220 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use reqwest::redirect::Policy;
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::json;
+use url::Url;
+
+use crate::config::json_schema;
+
+/// redirects followed when no policy is configured, same as the reqwest default
+const DEFAULT_MAX_REDIRECTS: usize = 10;
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Default, PartialEq, Eq)]
+pub struct TimeoutContext {
+    /// timeout for establishing the connection, e.g. `2s`
+    #[serde(
+        with = "humantime_serde",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    #[schemars(schema_with = "crate::config::schema::duration")]
+    connect: Option<Duration>,
+    /// timeout for the whole request including reading the body, e.g. `10s`
+    #[serde(
+        with = "humantime_serde",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    #[schemars(schema_with = "crate::config::schema::duration")]
+    total: Option<Duration>,
+}
+
+impl TimeoutContext {
+    pub fn connect(&self) -> Option<Duration> {
+        self.connect
+    }
+
+    pub fn total(&self) -> Option<Duration> {
+        self.total
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+pub struct RetryContext {
+    /// how many times a failed request is retried
+    count: usize,
+    /// delay before the first retry, doubled for every further retry
+    #[serde(with = "humantime_serde", default = "default_backoff")]
+    #[schemars(schema_with = "crate::config::schema::duration")]
+    backoff: Duration,
+    /// status codes that are retried in addition to connection errors and timeouts
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    statuses: Vec<u16>,
+}
+
+fn default_backoff() -> Duration {
+    Duration::from_millis(100)
+}
+
+impl Default for RetryContext {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            backoff: default_backoff(),
+            statuses: vec![],
+        }
+    }
+}
+
+impl RetryContext {
+    pub fn new(count: usize, backoff: Duration, statuses: Vec<u16>) -> Self {
+        Self {
+            count,
+            backoff,
+            statuses,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn should_retry_status(&self, status: u16) -> bool {
+        self.statuses.contains(&status)
+    }
+
+    pub fn should_retry_error(&self, err: &reqwest::Error) -> bool {
+        err.is_connect() || err.is_timeout()
+    }
+
+    /// exponential backoff delay before the given retry, starting at 0
+    pub fn delay(&self, retry: usize) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(retry.min(31) as u32))
+    }
+}
+
+/// `none` to not follow redirects, or the max number of redirects to follow
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedirectPolicy {
+    None,
+    Follow(usize),
+}
+
+impl Default for RedirectPolicy {
+    fn default() -> Self {
+        Self::Follow(DEFAULT_MAX_REDIRECTS)
+    }
+}
+
+impl RedirectPolicy {
+    /// build the reqwest policy, every followed url is recorded into `chain`
+    pub fn to_policy(self, chain: Arc<Mutex<Vec<Url>>>) -> Policy {
+        match self {
+            RedirectPolicy::None => Policy::none(),
+            RedirectPolicy::Follow(max) => Policy::custom(move |attempt| {
+                if attempt.previous().len() > max {
+                    return attempt.error(format!("too many redirects (max {})", max));
+                }
+                chain.lock().unwrap().push(attempt.url().clone());
+                attempt.follow()
+            }),
+        }
+    }
+}
+
+impl Serialize for RedirectPolicy {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            RedirectPolicy::None => serializer.serialize_str("none"),
+            RedirectPolicy::Follow(max) => serializer.serialize_u64(*max as u64),
+        }
+    }
+}
+
+impl JsonSchema for RedirectPolicy {
+    fn schema_name() -> String {
+        "RedirectPolicy".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema(json!({
+            "description": "`none`, `follow` or the max number of redirects to follow",
+            "anyOf": [
+                {"type": "string", "enum": ["none", "follow"]},
+                {"type": "integer", "minimum": 0},
+            ],
+        }))
+    }
+}
+
+impl<'de> Deserialize<'de> for RedirectPolicy {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PolicyVisitor;
+
+        impl de::Visitor<'_> for PolicyVisitor {
+            type Value = RedirectPolicy;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("`none`, `follow` or the max number of redirects to follow")
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                Ok(match v {
+                    0 => RedirectPolicy::None,
+                    n => RedirectPolicy::Follow(n as usize),
+                })
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+                u64::try_from(v)
+                    .map_err(|_| E::custom("redirect count must not be negative"))
+                    .and_then(|v| self.visit_u64(v))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                match v {
+                    "none" => Ok(RedirectPolicy::None),
+                    "follow" => Ok(RedirectPolicy::default()),
+                    _ => Err(E::invalid_value(de::Unexpected::Str(v), &self)),
+                }
+            }
+        }
+
+        deserializer.deserialize_any(PolicyVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redirect_policy_should_deserialize() {
+        let policy: RedirectPolicy = serde_yaml::from_str("none").unwrap();
+        assert_eq!(policy, RedirectPolicy::None);
+        let policy: RedirectPolicy = serde_yaml::from_str("3").unwrap();
+        assert_eq!(policy, RedirectPolicy::Follow(3));
+        let policy: RedirectPolicy = serde_yaml::from_str("follow").unwrap();
+        assert_eq!(policy, RedirectPolicy::Follow(DEFAULT_MAX_REDIRECTS));
+        assert!(serde_yaml::from_str::<RedirectPolicy>("always").is_err());
+        assert_eq!(
+            serde_yaml::to_string(&RedirectPolicy::None).unwrap(),
+            "none\n"
+        );
+    }
+
+    #[test]
+    fn retry_delay_should_back_off_exponentially() {
+        let retry: RetryContext = serde_yaml::from_str("count: 3\nbackoff: 200ms\n").unwrap();
+        assert_eq!(retry.delay(0), Duration::from_millis(200));
+        assert_eq!(retry.delay(1), Duration::from_millis(400));
+        assert_eq!(retry.delay(2), Duration::from_millis(800));
+    }
+}
@@ -0,0 +1,422 @@
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    sync::{Arc, Mutex, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use http::HeaderValue;
+use reqwest::cookie::CookieStore;
+use url::Url;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    /// domain without the leading dot
+    pub domain: String,
+    /// whether the cookie is also sent to subdomains of `domain`
+    pub include_subdomains: bool,
+    pub path: String,
+    pub secure: bool,
+    pub http_only: bool,
+    /// expiry as unix seconds, `None` for session cookies
+    pub expires: Option<u64>,
+}
+
+impl Cookie {
+    /// parse a `Set-Cookie` header received from `url`
+    pub fn parse(header: &str, url: &Url) -> Option<Self> {
+        let mut parts = header.split(';');
+        let (name, value) = parts.next()?.split_once('=')?;
+        let name = name.trim();
+        if name.is_empty() {
+            return None;
+        }
+
+        let mut cookie = Cookie {
+            name: name.to_string(),
+            value: value.trim().trim_matches('"').to_string(),
+            domain: url.host_str()?.to_string(),
+            include_subdomains: false,
+            path: default_path(url.path()),
+            secure: false,
+            http_only: false,
+            expires: None,
+        };
+        let mut max_age = None;
+        for attr in parts {
+            let (key, value) = match attr.split_once('=') {
+                Some((k, v)) => (k.trim(), v.trim()),
+                None => (attr.trim(), ""),
+            };
+            match key.to_ascii_lowercase().as_str() {
+                "domain" if !value.is_empty() => {
+                    cookie.domain = value.trim_start_matches('.').to_ascii_lowercase();
+                    cookie.include_subdomains = true;
+                }
+                "path" if value.starts_with('/') => cookie.path = value.to_string(),
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                "max-age" => max_age = value.parse::<i64>().ok(),
+                "expires" => {
+                    if let Ok(time) = httpdate::parse_http_date(value) {
+                        cookie.expires = Some(unix_secs(time));
+                    }
+                }
+                _ => {}
+            }
+        }
+        // a server can only set cookies for its own domain or a parent of it (RFC 6265 5.3)
+        if cookie.include_subdomains && !domain_matches(url.host_str()?, &cookie.domain) {
+            return None;
+        }
+        // max-age takes precedence over expires
+        if let Some(max_age) = max_age {
+            cookie.expires = Some((now() as i64 + max_age).max(0) as u64);
+        }
+        Some(cookie)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires.is_some_and(|expires| expires <= now())
+    }
+
+    pub fn matches(&self, url: &Url) -> bool {
+        let host = url.host_str().unwrap_or_default();
+        let domain_match = match self.include_subdomains {
+            true => domain_matches(host, &self.domain),
+            false => host == self.domain,
+        };
+        let path = url.path();
+        let path_match = path == self.path
+            || (path.starts_with(&self.path)
+                && (self.path.ends_with('/') || path[self.path.len()..].starts_with('/')));
+        domain_match
+            && path_match
+            && (!self.secure || url.scheme() == "https")
+            && !self.is_expired()
+    }
+
+    /// parse a line of a Netscape cookie file
+    fn from_netscape(line: &str) -> Option<Self> {
+        let (line, http_only) = match line.strip_prefix("#HttpOnly_") {
+            Some(line) => (line, true),
+            None => (line, false),
+        };
+        let fields: Vec<_> = line.split('\t').collect();
+        if fields.len() != 7 {
+            return None;
+        }
+        let expires = fields[4].parse::<u64>().ok()?;
+        Some(Cookie {
+            domain: fields[0].trim_start_matches('.').to_string(),
+            include_subdomains: fields[1].eq_ignore_ascii_case("TRUE"),
+            path: fields[2].to_string(),
+            secure: fields[3].eq_ignore_ascii_case("TRUE"),
+            expires: (expires != 0).then_some(expires),
+            name: fields[5].to_string(),
+            value: fields[6].to_string(),
+            http_only,
+        })
+    }
+
+    fn to_netscape(&self) -> String {
+        let bool_text = |v: bool| if v { "TRUE" } else { "FALSE" };
+        format!(
+            "{}{}{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            if self.http_only { "#HttpOnly_" } else { "" },
+            if self.include_subdomains { "." } else { "" },
+            self.domain,
+            bool_text(self.include_subdomains),
+            self.path,
+            bool_text(self.secure),
+            self.expires.unwrap_or(0),
+            self.name,
+            self.value
+        )
+    }
+}
+
+/// the host is the domain or a subdomain of it, ip addresses only match themselves
+fn domain_matches(host: &str, domain: &str) -> bool {
+    let host = host.to_ascii_lowercase();
+    host == domain
+        || (host.ends_with(&format!(".{}", domain)) && host.parse::<std::net::IpAddr>().is_err())
+}
+
+/// RFC 6265 default path: the request path up to, but not including, the last `/`
+fn default_path(path: &str) -> String {
+    match path.rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(idx) => path[..idx].to_string(),
+    }
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn now() -> u64 {
+    unix_secs(SystemTime::now())
+}
+
+/// cookie store that can be loaded from and saved to a Netscape cookie file
+#[derive(Debug, Default)]
+pub struct CookieJar {
+    cookies: RwLock<Vec<Cookie>>,
+}
+
+impl CookieJar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&self, cookie: Cookie) {
+        let mut cookies = self.cookies.write().unwrap();
+        cookies.retain(|c| {
+            !(c.name == cookie.name && c.domain == cookie.domain && c.path == cookie.path)
+        });
+        if !cookie.is_expired() {
+            cookies.push(cookie);
+        }
+    }
+
+    /// all cookies in the jar, including the ones not sent to any url anymore
+    pub fn list(&self) -> Vec<Cookie> {
+        self.cookies.read().unwrap().clone()
+    }
+
+    pub fn from_netscape(content: &str) -> Self {
+        let jar = Self::new();
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || (line.starts_with('#') && !line.starts_with("#HttpOnly_")) {
+                continue;
+            }
+            if let Some(cookie) = Cookie::from_netscape(line) {
+                jar.insert(cookie);
+            }
+        }
+        jar
+    }
+
+    pub fn to_netscape(&self) -> String {
+        let mut output = String::from("# Netscape HTTP Cookie File\n");
+        for cookie in self.cookies.read().unwrap().iter() {
+            if !cookie.is_expired() {
+                output.push_str(&cookie.to_netscape());
+                output.push('\n');
+            }
+        }
+        output
+    }
+
+    pub fn load(path: &str) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read cookie file: {}", path))?;
+        Ok(Self::from_netscape(&content))
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        std::fs::write(path, self.to_netscape())
+            .with_context(|| format!("failed to write cookie file: {}", path))
+    }
+}
+
+impl CookieStore for CookieJar {
+    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &Url) {
+        for header in cookie_headers {
+            if let Some(cookie) = header.to_str().ok().and_then(|h| Cookie::parse(h, url)) {
+                self.insert(cookie);
+            }
+        }
+    }
+
+    fn cookies(&self, url: &Url) -> Option<HeaderValue> {
+        let cookies = self.cookies.read().unwrap();
+        let mut matched: Vec<_> = cookies.iter().filter(|c| c.matches(url)).collect();
+        if matched.is_empty() {
+            return None;
+        }
+        // cookies with longer paths are listed first
+        matched.sort_by_key(|c| Reverse(c.path.len()));
+        let value = matched
+            .iter()
+            .map(|c| format!("{}={}", c.name, c.value))
+            .collect::<Vec<_>>()
+            .join("; ");
+        HeaderValue::from_str(&value).ok()
+    }
+}
+
+type CookieJars = Arc<Mutex<HashMap<String, Arc<CookieJar>>>>;
+
+/// state shared by the requests of a run, every side keeps its own cookie jar
+#[derive(Debug, Default, Clone)]
+pub struct Session {
+    cookies: Option<CookieJars>,
+}
+
+impl Session {
+    /// a session without a cookie store, cookies are neither sent nor kept
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_cookies() -> Self {
+        Self {
+            cookies: Some(Default::default()),
+        }
+    }
+
+    /// the cookie jar of the given side, `None` if cookies are disabled
+    pub fn cookie_jar(&self, side: &str) -> Option<Arc<CookieJar>> {
+        let jars = self.cookies.as_ref()?;
+        let mut jars = jars.lock().unwrap();
+        Some(jars.entry(side.to_string()).or_default().clone())
+    }
+
+    pub fn load_cookies(&self, side: &str, path: &str) -> Result<()> {
+        if let Some(jars) = &self.cookies {
+            let jar = CookieJar::load(path)?;
+            jars.lock().unwrap().insert(side.to_string(), Arc::new(jar));
+        }
+        Ok(())
+    }
+
+    pub fn save_cookies(&self, side: &str, path: &str) -> Result<()> {
+        match self.cookie_jar(side) {
+            Some(jar) => jar.save(path),
+            None => Ok(()),
+        }
+    }
+}
+
+/// render a `Set-Cookie` header as `name: value; attributes`, the attributes are sorted
+/// and the volatile `expires` date is reduced to its presence so that only real changes show up
+pub fn set_cookie_text(header: &str) -> Option<(String, String)> {
+    let mut parts = header.split(';');
+    let (name, value) = parts.next()?.split_once('=')?;
+    let mut attrs: Vec<String> = parts
+        .filter_map(|attr| {
+            let attr = attr.trim();
+            let (key, value) = match attr.split_once('=') {
+                Some((k, v)) => (k.trim().to_ascii_lowercase(), Some(v.trim())),
+                None => (attr.to_ascii_lowercase(), None),
+            };
+            match (key.as_str(), value) {
+                ("", _) => None,
+                ("expires", _) => Some(key),
+                (_, Some(value)) => Some(format!("{}={}", key, value)),
+                (_, None) => Some(key),
+            }
+        })
+        .collect();
+    attrs.sort();
+    let mut text = format!("{:?}", value.trim());
+    for attr in attrs {
+        text.push_str("; ");
+        text.push_str(&attr);
+    }
+    Some((name.trim().to_string(), text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    #[test]
+    fn cookie_parse_should_work() {
+        let cookie = Cookie::parse(
+            "sid=abc; Domain=.example.com; Path=/app; Secure; HttpOnly; Max-Age=3600",
+            &url("https://www.example.com/app/login"),
+        )
+        .unwrap();
+        assert_eq!(cookie.name, "sid");
+        assert_eq!(cookie.value, "abc");
+        assert_eq!(cookie.domain, "example.com");
+        assert!(cookie.include_subdomains && cookie.secure && cookie.http_only);
+        assert_eq!(cookie.path, "/app");
+        assert!(cookie.expires.unwrap() > now());
+
+        let cookie = Cookie::parse("lang=en", &url("http://example.com/a/b")).unwrap();
+        assert_eq!(cookie.domain, "example.com");
+        assert!(!cookie.include_subdomains);
+        assert_eq!(cookie.path, "/a");
+        assert_eq!(cookie.expires, None);
+
+        let url = self::url("https://www.example.com/");
+        assert!(Cookie::parse("sid=abc; Domain=evil.com", &url).is_none());
+        assert!(Cookie::parse("sid=abc; Domain=ample.com", &url).is_none());
+        assert!(Cookie::parse("sid=abc; Domain=api.www.example.com", &url).is_none());
+        assert!(Cookie::parse("sid=abc; Domain=WWW.example.com", &url).is_some());
+    }
+
+    #[test]
+    fn cookie_jar_should_send_matching_cookies() {
+        let jar = CookieJar::new();
+        let headers = [
+            HeaderValue::from_static("sid=abc; Path=/; Domain=example.com"),
+            HeaderValue::from_static("token=xyz; Path=/api; Secure"),
+            HeaderValue::from_static("old=1; Max-Age=0"),
+        ];
+        jar.set_cookies(&mut headers.iter(), &url("https://example.com/login"));
+
+        let cookies = jar.cookies(&url("https://example.com/api/users")).unwrap();
+        assert_eq!(cookies, "token=xyz; sid=abc");
+        let cookies = jar.cookies(&url("http://www.example.com/api")).unwrap();
+        assert_eq!(cookies, "sid=abc");
+        assert!(jar.cookies(&url("https://other.com/")).is_none());
+    }
+
+    #[test]
+    fn netscape_format_should_round_trip() {
+        let content = "# Netscape HTTP Cookie File\n\
+                       .example.com\tTRUE\t/\tFALSE\t0\tsid\tabc\n\
+                       #HttpOnly_api.example.com\tFALSE\t/v1\tTRUE\t4102444800\ttoken\txyz\n";
+        let jar = CookieJar::from_netscape(content);
+        let cookies = jar.list();
+        assert_eq!(cookies.len(), 2);
+        assert!(cookies[0].include_subdomains && cookies[0].expires.is_none());
+        assert!(cookies[1].http_only && cookies[1].secure);
+        assert_eq!(jar.to_netscape(), content);
+    }
+
+    #[test]
+    fn session_should_keep_jars_per_side() {
+        let session = Session::with_cookies();
+        let jar = session.cookie_jar("req1").unwrap();
+        jar.set_cookies(
+            &mut [HeaderValue::from_static("sid=1")].iter(),
+            &url("http://example.com/"),
+        );
+        assert!(session
+            .cookie_jar("req1")
+            .unwrap()
+            .cookies(&url("http://example.com/"))
+            .is_some());
+        assert!(session
+            .cookie_jar("req2")
+            .unwrap()
+            .cookies(&url("http://example.com/"))
+            .is_none());
+        assert!(Session::new().cookie_jar("req1").is_none());
+    }
+
+    #[test]
+    fn set_cookie_text_should_normalize_attributes() {
+        let (name, text) = set_cookie_text(
+            "sid=abc; Path=/; Expires=Wed, 21 Oct 2015 07:28:00 GMT; HttpOnly; SameSite=Lax",
+        )
+        .unwrap();
+        assert_eq!(name, "sid");
+        assert_eq!(text, r#""abc"; expires; httponly; path=/; samesite=Lax"#);
+    }
+}
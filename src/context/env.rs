@@ -0,0 +1,162 @@
+use std::{borrow::Cow, sync::OnceLock};
+
+use anyhow::{anyhow, Result};
+use http::HeaderMap;
+use indexmap::IndexMap;
+use regex::{Captures, Regex};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// a named target the requests of an item can be sent to
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Default, PartialEq, Eq)]
+pub struct Environment {
+    /// prefix of the relative request urls, e.g. `https://staging.example.com/api`
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    base_url: Option<String>,
+    /// headers sent with every request unless the request sets them itself
+    #[serde(
+        skip_serializing_if = "HeaderMap::is_empty",
+        with = "http_serde::header_map",
+        default
+    )]
+    #[schemars(schema_with = "crate::config::schema::header_map")]
+    headers: HeaderMap,
+    /// values of the `{{name}}` placeholders
+    #[serde(skip_serializing_if = "IndexMap::is_empty", default)]
+    variables: IndexMap<String, String>,
+}
+
+fn placeholder() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\{\{\s*([\w.-]+)\s*\}\}").unwrap())
+}
+
+/// names of the `{{name}}` placeholders in a text
+pub(crate) fn placeholders(text: &str) -> impl Iterator<Item = &str> {
+    placeholder()
+        .captures_iter(text)
+        .map(|caps| caps.get(1).unwrap().as_str())
+}
+
+/// the text with every placeholder replaced by `value`, e.g. to check its syntax
+pub(crate) fn fill_placeholders<'a>(text: &'a str, value: &str) -> Cow<'a, str> {
+    placeholder().replace_all(text, value)
+}
+
+impl Environment {
+    pub fn new(base_url: Option<String>, variables: IndexMap<String, String>) -> Self {
+        Self {
+            base_url,
+            headers: HeaderMap::new(),
+            variables,
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if let Some(base) = &self.base_url {
+            Url::parse(base).map_err(|e| anyhow!("invalid base_url {}: {}", base, e))?;
+        }
+        Ok(())
+    }
+
+    pub fn has_base_url(&self) -> bool {
+        self.base_url.is_some()
+    }
+
+    pub fn has_variable(&self, name: &str) -> bool {
+        self.variables.contains_key(name)
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// join a relative url to the base url, absolute urls are kept as they are
+    pub fn resolve_url(&self, url: &str) -> String {
+        match &self.base_url {
+            Some(base) if !url.contains("://") => format!(
+                "{}/{}",
+                base.trim_end_matches('/'),
+                url.trim_start_matches('/')
+            ),
+            _ => url.to_string(),
+        }
+    }
+
+    /// replace the `{{name}}` placeholders by the variables of the environment
+    pub fn substitute(&self, text: &str) -> Result<String> {
+        let mut missing = None;
+        let output =
+            placeholder().replace_all(text, |caps: &Captures| match self.variables.get(&caps[1]) {
+                Some(v) => v.clone(),
+                None => {
+                    missing.get_or_insert_with(|| caps[1].to_string());
+                    caps[0].to_string()
+                }
+            });
+        match missing {
+            Some(name) => Err(anyhow!("undefined variable {} in {}", name, text)),
+            None => Ok(output.into_owned()),
+        }
+    }
+
+    /// replace the placeholders in every string of a json value
+    pub fn substitute_json(&self, value: &mut serde_json::Value) -> Result<()> {
+        match value {
+            serde_json::Value::String(s) => *s = self.substitute(s)?,
+            serde_json::Value::Array(arr) => {
+                for v in arr {
+                    self.substitute_json(v)?;
+                }
+            }
+            serde_json::Value::Object(obj) => {
+                for v in obj.values_mut() {
+                    self.substitute_json(v)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn env() -> Environment {
+        serde_yaml::from_str(
+            "base_url: https://staging.example.com/api/\nvariables:\n  id: '42'\n  user: alice\n",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn resolve_url_should_work() {
+        let env = env();
+        assert_eq!(
+            env.resolve_url("/todos/1"),
+            "https://staging.example.com/api/todos/1"
+        );
+        assert_eq!(
+            env.resolve_url("https://example.com/x"),
+            "https://example.com/x"
+        );
+        assert_eq!(Environment::default().resolve_url("/x"), "/x");
+    }
+
+    #[test]
+    fn substitute_should_work() {
+        let env = env();
+        assert_eq!(env.substitute("/todos/{{id}}").unwrap(), "/todos/42");
+        assert_eq!(env.substitute("{{ user }}-{{id}}").unwrap(), "alice-42");
+        assert!(env.substitute("/users/{{missing}}").is_err());
+
+        let mut body = json!({"owner": "{{user}}", "tags": ["{{id}}"], "n": 1});
+        env.substitute_json(&mut body).unwrap();
+        assert_eq!(body, json!({"owner": "alice", "tags": ["42"], "n": 1}));
+    }
+}
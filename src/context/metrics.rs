@@ -0,0 +1,191 @@
+use std::time::Duration;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// timing and size of a single response, captured by `RequestContext::send`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResponseMetrics {
+    /// time from sending the request until the response headers arrived
+    pub headers: Duration,
+    /// time from sending the request until the whole body was read
+    pub total: Duration,
+    /// size of the (decoded) response body in bytes
+    pub size: usize,
+}
+
+impl ResponseMetrics {
+    pub fn new(headers: Duration, total: Duration, size: usize) -> Self {
+        Self {
+            headers,
+            total,
+            size,
+        }
+    }
+
+    /// average a set of samples taken over several repetitions
+    pub fn average(samples: &[ResponseMetrics]) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        let n = samples.len() as u32;
+        let headers = samples.iter().map(|m| m.headers).sum::<Duration>() / n;
+        let total = samples.iter().map(|m| m.total).sum::<Duration>() / n;
+        let size = samples.iter().map(|m| m.size).sum::<usize>() / samples.len();
+        Self::new(headers, total, size)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Default, PartialEq)]
+pub struct PerfContext {
+    /// how many times each request is sent, metrics are averaged over all repetitions
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    repeat: Option<usize>,
+    /// max allowed total latency increase compared to the baseline, in percent
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    max_slowdown: Option<f64>,
+    /// max allowed body size increase compared to the baseline, in percent
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    max_size_increase: Option<f64>,
+}
+
+impl PerfContext {
+    pub fn new(
+        repeat: Option<usize>,
+        max_slowdown: Option<f64>,
+        max_size_increase: Option<f64>,
+    ) -> Self {
+        Self {
+            repeat,
+            max_slowdown,
+            max_size_increase,
+        }
+    }
+
+    pub fn repeat(&self) -> usize {
+        self.repeat.unwrap_or(1).max(1)
+    }
+
+    /// check the metrics of `other` against `base`, return the violated thresholds
+    pub fn check(
+        &self,
+        name: &str,
+        base: &ResponseMetrics,
+        other: &ResponseMetrics,
+    ) -> Vec<String> {
+        let mut failures = vec![];
+        if let Some(max) = self.max_slowdown {
+            let change = percent_change(base.total.as_secs_f64(), other.total.as_secs_f64());
+            if change > max {
+                failures.push(format!(
+                    "{} is {:.1}% slower than the baseline (max {}%)",
+                    name, change, max
+                ));
+            }
+        }
+        if let Some(max) = self.max_size_increase {
+            let change = percent_change(base.size as f64, other.size as f64);
+            if change > max {
+                failures.push(format!(
+                    "{} body is {:.1}% larger than the baseline (max {}%)",
+                    name, change, max
+                ));
+            }
+        }
+        failures
+    }
+}
+
+fn percent_change(base: f64, other: f64) -> f64 {
+    if base == 0.0 {
+        return if other == 0.0 { 0.0 } else { f64::INFINITY };
+    }
+    (other - base) / base * 100.0
+}
+
+fn format_change(base: f64, other: f64) -> String {
+    let change = percent_change(base, other);
+    if change.is_infinite() {
+        "n/a".to_string()
+    } else {
+        format!("{:+.1}%", change)
+    }
+}
+
+/// render the metrics side by side, every column after the first one is compared to the first
+pub fn metrics_text(columns: &[(&str, ResponseMetrics)]) -> String {
+    let mut output = format!("{:<10}", "");
+    for (name, _) in columns {
+        output.push_str(&format!("{:>22}", name));
+    }
+    output.push('\n');
+
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+    let values = |f: &dyn Fn(&ResponseMetrics) -> f64| -> Vec<f64> {
+        columns.iter().map(|(_, m)| f(m)).collect()
+    };
+    let rows = [
+        ("headers", values(&|m| ms(m.headers)), 1, "ms"),
+        ("total", values(&|m| ms(m.total)), 1, "ms"),
+        ("size", values(&|m| m.size as f64), 0, "B"),
+    ];
+    for (label, values, precision, unit) in rows {
+        output.push_str(&format!("{:<10}", label));
+        for (idx, value) in values.iter().enumerate() {
+            let mut cell = format!("{:.*}{}", precision, value, unit);
+            if idx > 0 {
+                cell.push_str(&format!(" ({})", format_change(values[0], *value)));
+            }
+            output.push_str(&format!("{:>22}", cell));
+        }
+        output.push('\n');
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics(headers: u64, total: u64, size: usize) -> ResponseMetrics {
+        ResponseMetrics::new(
+            Duration::from_millis(headers),
+            Duration::from_millis(total),
+            size,
+        )
+    }
+
+    #[test]
+    fn average_should_work() {
+        let avg = ResponseMetrics::average(&[metrics(10, 20, 100), metrics(30, 40, 200)]);
+        assert_eq!(avg, metrics(20, 30, 150));
+        assert_eq!(ResponseMetrics::average(&[]), ResponseMetrics::default());
+    }
+
+    #[test]
+    fn perf_check_should_report_violations() {
+        let perf = PerfContext::new(Some(3), Some(20.0), Some(10.0));
+        let base = metrics(10, 100, 1000);
+        assert!(perf
+            .check("req2", &base, &metrics(10, 119, 1099))
+            .is_empty());
+
+        let failures = perf.check("req2", &base, &metrics(10, 130, 1200));
+        assert_eq!(failures.len(), 2);
+        assert!(failures[0].contains("30.0% slower"));
+        assert!(failures[1].contains("20.0% larger"));
+    }
+
+    #[test]
+    fn metrics_text_should_work() {
+        let text = metrics_text(&[
+            ("req1", metrics(10, 100, 1000)),
+            ("req2", metrics(10, 150, 1000)),
+        ]);
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[2].starts_with("total"));
+        assert!(lines[2].ends_with("150.0ms (+50.0%)"));
+        assert!(lines[3].ends_with("1000B (+0.0%)"));
+    }
+}
@@ -0,0 +1,49 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+/// build a raw http/1.1 response
+pub(crate) fn response(status: &str, headers: &[(&str, &str)], body: &str) -> String {
+    let mut output = format!("HTTP/1.1 {}\r\n", status);
+    for (k, v) in headers {
+        output.push_str(&format!("{}: {}\r\n", k, v));
+    }
+    output.push_str(&format!(
+        "content-length: {}\r\nconnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    ));
+    output
+}
+
+/// a local http server answering with the given responses in order, the last one is repeated.
+/// Returns the base url and the number of requests served so far
+pub(crate) async fn mock_server(responses: Vec<String>) -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let hits = Arc::new(AtomicUsize::new(0));
+    let counter = hits.clone();
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 8192];
+            let mut len = 0;
+            while !buf[..len].windows(4).any(|w| w == b"\r\n\r\n") {
+                match stream.read(&mut buf[len..]).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => len += n,
+                }
+            }
+            let hit = counter.fetch_add(1, Ordering::SeqCst);
+            let response = &responses[hit.min(responses.len() - 1)];
+            let _ = stream.write_all(response.as_bytes()).await;
+        }
+    });
+    (url, hits)
+}
@@ -1,12 +1,40 @@
+mod assertion;
+mod auth;
+mod client;
+mod cookie;
+mod env;
+mod metrics;
+#[cfg(test)]
+pub(crate) mod mock;
+mod noise;
 mod req;
 mod res;
+mod schema;
+mod sign;
+mod tls;
 
 use anyhow::Result;
 use http::{header, HeaderMap};
 use reqwest::Response;
 
+use crate::utils::remove_json_path;
+
+pub use assertion::{Assertions, JsonAssertion};
+pub use auth::{AuthContext, OAuth2Context, Secret};
+pub use client::{RedirectPolicy, RetryContext, TimeoutContext};
+pub use cookie::{set_cookie_text, Cookie, CookieJar, Session};
+pub use env::Environment;
+pub(crate) use env::{fill_placeholders, placeholders};
+pub use metrics::{metrics_text, PerfContext, ResponseMetrics};
+pub use noise::Noise;
 pub use req::RequestContext;
 pub use res::{ResponseContext, ResponseHandler};
+pub use schema::{load_schema, BodySchema};
+pub use sign::{
+    AwsCredentialsContext, AwsSigV4Signer, HmacAlgorithm, HmacSigner, SignContext,
+    SignatureEncoding, Signer,
+};
+pub use tls::{IdentityContext, TlsContext};
 
 fn resolve_content_type(headers: &HeaderMap) -> Option<&str> {
     headers
@@ -19,11 +47,28 @@ pub fn status_text(res: &Response) -> Result<String> {
     Ok(output)
 }
 
+/// `Set-Cookie` headers are rendered one line per cookie as `set-cookie[name]`, a single
+/// cookie can be skipped by adding `set-cookie[name]` to `skip_headers`
 pub fn headers_text(res: &Response, skip_headers: &[String]) -> Result<String> {
     let mut output = String::new();
+    let mut cookies = vec![];
     for (k, v) in res.headers().iter() {
-        if !skip_headers.iter().any(|s| s == k.as_str()) {
-            output.push_str(&format!("{}: {:?}\r\n", k, v));
+        if skip_headers.iter().any(|s| s == k.as_str()) {
+            continue;
+        }
+        if k == header::SET_COOKIE {
+            if let Some(cookie) = v.to_str().ok().and_then(set_cookie_text) {
+                cookies.push(cookie);
+                continue;
+            }
+        }
+        output.push_str(&format!("{}: {:?}\r\n", k, v));
+    }
+    cookies.sort();
+    for (name, text) in cookies {
+        let key = format!("set-cookie[{}]", name);
+        if !skip_headers.contains(&key) {
+            output.push_str(&format!("{}: {}\r\n", key, text));
         }
     }
     Ok(output)
@@ -33,18 +78,22 @@ pub async fn body_text(res: Response, skip_body: &[String]) -> Result<String> {
     let headers = res.headers().clone();
     let content_type = resolve_content_type(&headers);
     let text = res.text().await?;
-    match content_type.as_deref() {
-        Some("application/json") => filter_json(&text, &skip_body),
+    match content_type {
+        Some("application/json") => filter_json(&text, skip_body),
         _ => Ok(text),
     }
 }
 
+/// `skip_body` entries are top level keys or dot separated paths like `data.items.0.id`
 fn filter_json(text: &str, skip_body: &[String]) -> Result<String> {
     let mut json: serde_json::Value = serde_json::from_str(text)?;
-    if let serde_json::Value::Object(ref mut obj) = json {
-        for key in skip_body {
-            obj.remove(key);
+    for key in skip_body {
+        if let serde_json::Value::Object(ref mut obj) = json {
+            if obj.remove(key).is_some() {
+                continue;
+            }
         }
+        remove_json_path(&mut json, key);
     }
     Ok(serde_json::to_string_pretty(&json)?)
 }
@@ -0,0 +1,200 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use http::{header, HeaderMap};
+use serde_json::Value;
+
+use super::{set_cookie_text, ResponseContext, ResponseHandler};
+
+/// fields that differ between two responses to the same request, they are
+/// non-deterministic and not worth reporting when diffing two endpoints
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Noise {
+    /// header names, single cookies are reported as `set-cookie[name]`
+    headers: BTreeSet<String>,
+    /// dot separated json paths of the body
+    body: BTreeSet<String>,
+}
+
+impl Noise {
+    /// compare the primary and secondary responses of the same request
+    pub fn detect(primary: &ResponseHandler, secondary: &ResponseHandler) -> Self {
+        Self {
+            headers: header_noise(primary.headers(), secondary.headers()),
+            body: body_noise(primary.body(), secondary.body()),
+        }
+    }
+
+    pub fn merge(&mut self, other: Noise) {
+        self.headers.extend(other.headers);
+        self.body.extend(other.body);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty() && self.body.is_empty()
+    }
+
+    pub fn headers(&self) -> impl Iterator<Item = &str> {
+        self.headers.iter().map(|s| s.as_str())
+    }
+
+    pub fn body(&self) -> impl Iterator<Item = &str> {
+        self.body.iter().map(|s| s.as_str())
+    }
+
+    /// the response context with the noisy fields added to the skipped ones
+    pub fn apply(&self, ctx: &ResponseContext) -> ResponseContext {
+        let mut ctx = ctx.clone();
+        ctx.skip(self.headers.iter().cloned(), self.body.iter().cloned());
+        ctx
+    }
+
+    /// one line summary of the ignored fields, e.g. `noise: headers date; body ts`
+    pub fn summary(&self) -> String {
+        let mut parts = vec![];
+        if !self.headers.is_empty() {
+            parts.push(format!("headers {}", join(&self.headers)));
+        }
+        if !self.body.is_empty() {
+            parts.push(format!("body {}", join(&self.body)));
+        }
+        format!("noise: {}", parts.join("; "))
+    }
+}
+
+fn join(set: &BTreeSet<String>) -> String {
+    set.iter()
+        .map(|s| s.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn header_noise(a: &HeaderMap, b: &HeaderMap) -> BTreeSet<String> {
+    let mut noise = BTreeSet::new();
+    for name in a.keys().chain(b.keys()) {
+        if name == header::SET_COOKIE {
+            let (a, b) = (cookies(a), cookies(b));
+            for cookie in a.keys().chain(b.keys()) {
+                if a.get(cookie) != b.get(cookie) {
+                    noise.insert(format!("set-cookie[{}]", cookie));
+                }
+            }
+        } else if !a.get_all(name).iter().eq(b.get_all(name).iter()) {
+            noise.insert(name.to_string());
+        }
+    }
+    noise
+}
+
+fn cookies(headers: &HeaderMap) -> BTreeMap<String, String> {
+    headers
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok().and_then(set_cookie_text))
+        .collect()
+}
+
+/// json leaf paths with different values, bodies which are not json are not classified
+fn body_noise(a: &[u8], b: &[u8]) -> BTreeSet<String> {
+    let mut noise = BTreeSet::new();
+    if let (Ok(a), Ok(b)) = (
+        serde_json::from_slice::<Value>(a),
+        serde_json::from_slice::<Value>(b),
+    ) {
+        json_noise(&a, &b, "", &mut noise);
+    }
+    noise
+}
+
+fn json_noise(a: &Value, b: &Value, path: &str, noise: &mut BTreeSet<String>) {
+    let child = |key: &str| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", path, key)
+        }
+    };
+    match (a, b) {
+        (Value::Object(a), Value::Object(b)) => {
+            for key in a.keys().chain(b.keys()) {
+                match (a.get(key), b.get(key)) {
+                    (Some(va), Some(vb)) => json_noise(va, vb, &child(key), noise),
+                    _ => {
+                        noise.insert(child(key));
+                    }
+                }
+            }
+        }
+        (Value::Array(a), Value::Array(b)) if a.len() == b.len() => {
+            for (idx, (va, vb)) in a.iter().zip(b.iter()).enumerate() {
+                json_noise(va, vb, &child(&idx.to_string()), noise);
+            }
+        }
+        // a differing root can't be skipped, so it is left to the diff
+        _ if a != b && !path.is_empty() => {
+            noise.insert(path.to_string());
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderValue;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn header_noise_should_work() {
+        let mut a = HeaderMap::new();
+        a.insert(
+            "date",
+            HeaderValue::from_static("Mon, 01 Jan 2024 00:00:00 GMT"),
+        );
+        a.insert("server", HeaderValue::from_static("nginx"));
+        a.insert("x-request-id", HeaderValue::from_static("1"));
+        a.append("set-cookie", HeaderValue::from_static("sid=1; Path=/"));
+        a.append("set-cookie", HeaderValue::from_static("lang=en; Path=/"));
+        let mut b = a.clone();
+        b.insert(
+            "date",
+            HeaderValue::from_static("Mon, 01 Jan 2024 00:00:01 GMT"),
+        );
+        b.remove("x-request-id");
+        b.remove("set-cookie");
+        b.append("set-cookie", HeaderValue::from_static("sid=2; Path=/"));
+        b.append("set-cookie", HeaderValue::from_static("lang=en; Path=/"));
+        let noise: Vec<_> = header_noise(&a, &b).into_iter().collect();
+        assert_eq!(noise, vec!["date", "set-cookie[sid]", "x-request-id"]);
+    }
+
+    #[test]
+    fn body_noise_should_work() {
+        let a =
+            json!({"id": 1, "ts": 1, "data": {"items": [{"id": 1, "nonce": "a"}], "tags": [1]}});
+        let b = json!({"id": 1, "ts": 2, "data": {"items": [{"id": 1, "nonce": "b"}], "tags": [1, 2]}, "trace": "x"});
+        let noise: Vec<_> = body_noise(a.to_string().as_bytes(), b.to_string().as_bytes())
+            .into_iter()
+            .collect();
+        assert_eq!(
+            noise,
+            vec!["data.items.0.nonce", "data.tags", "trace", "ts"]
+        );
+        assert!(body_noise(b"text 1", b"text 2").is_empty());
+    }
+
+    #[test]
+    fn noise_should_extend_skipped_fields() {
+        let mut noise = Noise::default();
+        noise.merge(Noise {
+            headers: ["date".to_string()].into(),
+            body: ["ts".to_string()].into(),
+        });
+        let ctx = noise.apply(&ResponseContext::new(vec!["server".into()], vec![]));
+        assert_eq!(
+            ctx,
+            ResponseContext::new(vec!["server".into(), "date".into()], vec!["ts".into()])
+        );
+        assert_eq!(noise.summary(), "noise: headers date; body ts");
+    }
+}
@@ -1,24 +1,41 @@
-use std::str::FromStr;
+use std::{
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
 
 use anyhow::{anyhow, Ok, Result};
 use http::{
     header::{self, HeaderName},
     HeaderMap, HeaderValue, Method,
 };
-use reqwest::Client;
+use indexmap::IndexMap;
+use reqwest::{Client, Proxy, Response, ResponseBuilderExt};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use url::Url;
 
 use crate::Args;
 
-use super::{resolve_content_type, ResponseHandler};
+use super::{
+    fill_placeholders, placeholders, resolve_content_type, AuthContext, CookieJar, Environment,
+    RedirectPolicy, ResponseHandler, ResponseMetrics, RetryContext, SignContext, Signer,
+    TimeoutContext, TlsContext,
+};
+use crate::{
+    config::Issues,
+    utils::{is_default, remove_json_path, set_json_path},
+};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
 pub struct RequestContext {
     #[serde(with = "http_serde::method", default)]
+    #[schemars(schema_with = "crate::config::schema::method")]
     method: Method,
-    url: Url,
+    /// absolute url, or a path relative to the `base_url` of an environment
+    url: String,
 
     #[serde(skip_serializing_if = "empty_json_value", default)]
     params: Option<serde_json::Value>,
@@ -28,21 +45,206 @@ pub struct RequestContext {
         with = "http_serde::header_map",
         default
     )]
+    #[schemars(schema_with = "crate::config::schema::header_map")]
     headers: HeaderMap,
 
     #[serde(skip_serializing_if = "empty_json_value", default)]
     body: Option<serde_json::Value>,
+
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    auth: Option<AuthContext>,
+
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    sign: Option<SignContext>,
+
+    #[serde(skip_serializing_if = "is_default", default)]
+    timeout: TimeoutContext,
+
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    retry: Option<RetryContext>,
+
+    #[serde(skip_serializing_if = "is_default", default)]
+    redirect: RedirectPolicy,
+
+    #[serde(skip_serializing_if = "is_default", default)]
+    tls: TlsContext,
+
+    /// http, https or socks5 proxy the request is sent through
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    proxy: Option<Url>,
+
+    /// connect to the given address instead of resolving the host, the port and
+    /// `Host` header still come from the url
+    #[serde(skip_serializing_if = "IndexMap::is_empty", default)]
+    resolve: IndexMap<String, IpAddr>,
+}
+
+fn apply_query(query: &mut serde_json::Value, args: &Args) {
+    for (k, v) in &args.query {
+        match v {
+            Some(v) => set_query(query, k, v.clone()),
+            None => {
+                if let Some(obj) = query.as_object_mut() {
+                    obj.remove(k);
+                }
+            }
+        }
+    }
+}
+
+/// query keys are never nested, but `key[]` appends to the values of the key
+fn set_query(query: &mut serde_json::Value, key: &str, value: serde_json::Value) {
+    match key.strip_suffix("[]") {
+        Some(key) => {
+            let values = &mut query[key];
+            match values {
+                serde_json::Value::Array(arr) => arr.push(value),
+                serde_json::Value::Null => *values = json!([value]),
+                v => *v = json!([v.take(), value]),
+            }
+        }
+        None => query[key] = value,
+    }
+}
+
+/// split a query key in the bracket syntax of serde_qs, e.g. `user[tags][0]` is `user` and
+/// `["tags", "0"]`. Keys which are not well formed are taken as is
+fn query_path(key: &str) -> (String, Vec<&str>) {
+    let Some(pos) = key.find('[').filter(|pos| *pos > 0 && key.ends_with(']')) else {
+        return (key.to_string(), vec![]);
+    };
+    let inner = &key[pos + 1..key.len() - 1];
+    let path: Vec<_> = inner.split("][").collect();
+    match path.iter().any(|s| s.contains(['[', ']'])) {
+        true => (key.to_string(), vec![]),
+        false => (key[..pos].to_string(), path),
+    }
+}
+
+/// insert a query value at the bracket path, repeated keys and `[]` collect the values in
+/// an array and numeric segments index into an array
+fn insert_query(target: &mut serde_json::Value, path: &[&str], value: serde_json::Value) {
+    use serde_json::Value;
+
+    let Some((segment, rest)) = path.split_first() else {
+        match target {
+            Value::Null => *target = value,
+            Value::Array(arr) => arr.push(value),
+            v => *v = json!([v.take(), value]),
+        }
+        return;
+    };
+    // `[]` appends, an index past the end appends too
+    let index = match segment.is_empty() {
+        true => Some(usize::MAX),
+        false => segment.parse::<usize>().ok(),
+    };
+    match index {
+        Some(index) => {
+            if !target.is_array() {
+                *target = match target.take() {
+                    Value::Null => json!([]),
+                    v => json!([v]),
+                };
+            }
+            let arr = target.as_array_mut().unwrap();
+            if index >= arr.len() {
+                arr.push(Value::Null);
+            }
+            let idx = index.min(arr.len() - 1);
+            insert_query(&mut arr[idx], rest, value);
+        }
+        None => {
+            if !target.is_object() {
+                *target = json!({});
+            }
+            let entry = target
+                .as_object_mut()
+                .unwrap()
+                .entry(segment.to_string())
+                .or_insert(Value::Null);
+            insert_query(entry, rest, value);
+        }
+    }
+}
+
+/// a query value is a number or bool only if it is written the way serde_qs writes it back,
+/// so `007` or `1.50` stay strings
+fn query_value(s: &str) -> serde_json::Value {
+    match s {
+        "true" => json!(true),
+        "false" => json!(false),
+        _ => serde_json::from_str::<serde_json::Number>(s)
+            .ok()
+            .filter(|n| n.to_string() == s)
+            .map_or_else(|| json!(s), serde_json::Value::Number),
+    }
+}
+
+/// serialize the query params with serde_qs, except that an array of plain values under a
+/// top level key repeats the key (`tag=a&tag=b`). A single value stays `tag[0]=a` so that it
+/// is still an array when parsed back
+fn query_string(query: &serde_json::Value) -> Result<String> {
+    let mut pairs = vec![];
+    for (k, v) in query.as_object().into_iter().flatten() {
+        match v {
+            serde_json::Value::Array(arr)
+                if arr.len() > 1 && arr.iter().all(|v| !v.is_array() && !v.is_object()) =>
+            {
+                for v in arr {
+                    pairs.push(serde_qs::to_string(&json!({ k: v }))?);
+                }
+            }
+            v => pairs.push(serde_qs::to_string(&json!({ k: v }))?),
+        }
+    }
+    pairs.retain(|p| !p.is_empty());
+    Ok(pairs.join("&"))
+}
+
+/// append the query params to the query of the url
+fn append_query(url: &mut Url, query: &serde_json::Value) -> Result<()> {
+    let query = query_string(query)?;
+    if query.is_empty() {
+        return Ok(());
+    }
+    let query = match url.query().filter(|q| !q.is_empty()) {
+        Some(q) => format!("{}&{}", q, query),
+        None => query,
+    };
+    url.set_query(Some(&query));
+    Ok(())
+}
+
+/// the strings of a json value with their dot separated paths
+fn json_strings(value: Option<&serde_json::Value>, path: &str, out: &mut Vec<(String, String)>) {
+    match value {
+        Some(serde_json::Value::String(s)) => out.push((path.to_string(), s.clone())),
+        Some(serde_json::Value::Array(arr)) => {
+            for (idx, v) in arr.iter().enumerate() {
+                json_strings(Some(v), &format!("{}.{}", path, idx), out);
+            }
+        }
+        Some(serde_json::Value::Object(obj)) => {
+            for (k, v) in obj {
+                json_strings(Some(v), &format!("{}.{}", path, k), out);
+            }
+        }
+        _ => {}
+    }
 }
 
 fn empty_json_value(v: &Option<serde_json::Value>) -> bool {
-    v.as_ref()
-        .map_or(true, |v| v.as_object().unwrap().is_empty())
+    match v {
+        Some(v) => v.as_object().unwrap().is_empty(),
+        None => true,
+    }
 }
 
 impl RequestContext {
     fn new(
         method: Method,
-        url: Url,
+        url: String,
         params: Option<serde_json::Value>,
         headers: HeaderMap,
         body: Option<serde_json::Value>,
@@ -53,6 +255,14 @@ impl RequestContext {
             params,
             headers,
             body,
+            auth: None,
+            sign: None,
+            timeout: TimeoutContext::default(),
+            retry: None,
+            redirect: RedirectPolicy::default(),
+            tls: TlsContext::default(),
+            proxy: None,
+            resolve: IndexMap::new(),
         }
     }
 }
@@ -62,16 +272,21 @@ impl FromStr for RequestContext {
 
     fn from_str(s: &str) -> Result<Self> {
         let mut url = Url::parse(s)?;
-        let pairs = url.query_pairs();
         let mut params = json!({});
-        for (key, value) in pairs {
-            params[&*key] = value.parse()?;
+        for (key, value) in url.query_pairs() {
+            let (key, path) = query_path(&key);
+            let target = params
+                .as_object_mut()
+                .unwrap()
+                .entry(key)
+                .or_insert(serde_json::Value::Null);
+            insert_query(target, &path, query_value(&value));
         }
         url.set_query(None);
 
         Ok(RequestContext::new(
             Method::GET,
-            url,
+            url.to_string(),
             Some(params),
             HeaderMap::new(),
             None,
@@ -81,28 +296,166 @@ impl FromStr for RequestContext {
 
 impl RequestContext {
     pub async fn send(&self, args: &Args) -> Result<ResponseHandler> {
-        let (headers, query, body) = self.generate(args)?;
-        let client = Client::new();
-        let builder = client.request(self.method.clone(), self.url.clone());
-        let request = builder.query(&query).headers(headers).body(body).build()?;
-        let res = client.execute(request).await?;
-        Ok(ResponseHandler::new(res))
+        self.send_with_cookies(args, None).await
+    }
+
+    /// send the request, cookies are taken from and stored into the given jar
+    pub async fn send_with_cookies(
+        &self,
+        args: &Args,
+        cookies: Option<Arc<CookieJar>>,
+    ) -> Result<ResponseHandler> {
+        let (mut headers, query, body) = self.generate(args)?;
+        if let Some(auth) = &self.auth {
+            auth.apply(&mut headers).await?;
+        }
+        let redirects = Arc::new(Mutex::new(vec![]));
+        let target = self.parsed_url()?;
+        let client = self.client(cookies, redirects.clone(), &target).await?;
+        let mut url = self.tls.url(&target)?;
+        append_query(&mut url, &query)?;
+        let retry = self.retry.clone().unwrap_or_default();
+
+        let mut attempt = 0;
+        let (res, start, headers_elapsed) = loop {
+            let builder = client.request(self.method.clone(), url.clone());
+            let mut request = builder
+                .headers(headers.clone())
+                .body(body.clone())
+                .build()?;
+            if let Some(signer) = &self.sign {
+                signer.sign(&mut request)?;
+            }
+
+            redirects.lock().unwrap().clear();
+            let start = Instant::now();
+            let result = client.execute(request).await;
+            let retryable = result.as_ref().map_or_else(
+                |e| retry.should_retry_error(e),
+                |res| retry.should_retry_status(res.status().as_u16()),
+            );
+            if attempt >= retry.count() || !retryable {
+                break (result?, start, start.elapsed());
+            }
+            tokio::time::sleep(retry.delay(attempt)).await;
+            attempt += 1;
+        };
+
+        // read the body eagerly so that the total time and size can be measured,
+        // then rebuild the response around the buffered body
+        let mut builder = http::Response::builder()
+            .status(res.status())
+            .version(res.version())
+            .url(res.url().clone());
+        if let Some(headers) = builder.headers_mut() {
+            *headers = res.headers().clone();
+        }
+        let body = res.bytes().await?;
+        let metrics = ResponseMetrics::new(headers_elapsed, start.elapsed(), body.len());
+        let res = Response::from(builder.body(body.clone())?);
+        let redirects = redirects.lock().unwrap().clone();
+        Ok(ResponseHandler::new(res, body, metrics).with_redirects(redirects))
+    }
+
+    async fn client(
+        &self,
+        cookies: Option<Arc<CookieJar>>,
+        redirects: Arc<Mutex<Vec<Url>>>,
+        url: &Url,
+    ) -> Result<Client> {
+        let mut builder = Client::builder().redirect(self.redirect.to_policy(redirects));
+        if let Some(jar) = cookies {
+            builder = builder.cookie_provider(jar);
+        }
+        if let Some(timeout) = self.timeout.connect() {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.timeout.total() {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(Proxy::all(proxy.as_str())?);
+        }
+        for (host, ip) in &self.resolve {
+            builder = builder.resolve(host, SocketAddr::new(*ip, 0));
+        }
+        let builder = self
+            .tls
+            .apply(builder, url, &self.resolve, self.proxy.is_some())
+            .await?;
+        Ok(builder.build()?)
+    }
+
+    pub fn with_method(mut self, method: Method) -> Self {
+        self.method = method;
+        self
+    }
+
+    /// add the headers to the request, replacing the ones of the same name
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers.extend(headers);
+        self
+    }
+
+    pub fn with_body(mut self, body: serde_json::Value) -> Self {
+        self.body = Some(body);
+        self
+    }
+
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// the url as configured, it may be relative or contain variables
+    pub fn raw_url(&self) -> &str {
+        &self.url
     }
 
     pub fn url(&self, args: &Args) -> Result<String> {
-        let mut url = self.url.clone();
+        let mut url = self.parsed_url()?;
         let mut query = self.params.clone().unwrap_or_else(|| json!({}));
-        for (k, v) in &args.query {
-            query[k] = v.parse()?;
+        apply_query(&mut query, args);
+        append_query(&mut url, &query)?;
+        Ok(url.to_string())
+    }
+
+    fn parsed_url(&self) -> Result<Url> {
+        Url::parse(&self.url).map_err(|e| match e {
+            url::ParseError::RelativeUrlWithoutBase => anyhow!(
+                "relative url {} needs an environment with a base_url",
+                self.url
+            ),
+            e => anyhow!("invalid url {}: {}", self.url, e),
+        })
+    }
+
+    /// resolve the request in an environment: relative urls are joined to its base url,
+    /// its headers are added unless set by the request and `{{name}}` variables are
+    /// replaced in the url, params, headers and body
+    pub fn with_environment(&self, env: &Environment) -> Result<Self> {
+        let mut req = self.clone();
+        req.url = env.resolve_url(&env.substitute(&self.url)?);
+        for (k, v) in env.headers() {
+            if !req.headers.contains_key(k) {
+                req.headers.insert(k.clone(), v.clone());
+            }
         }
-        if !query.as_object().unwrap().is_empty() {
-            let query = serde_qs::to_string(&query)?;
-            url.set_query(Some(&query));
+        for v in req.headers.values_mut() {
+            if let Some(s) = v.to_str().ok().filter(|s| s.contains("{{")) {
+                *v = HeaderValue::from_str(&env.substitute(s)?)?;
+            }
         }
-        Ok(url.to_string())
+        if let Some(params) = req.params.as_mut() {
+            env.substitute_json(params)?;
+        }
+        if let Some(body) = req.body.as_mut() {
+            env.substitute_json(body)?;
+        }
+        Ok(req)
     }
 
     pub fn validate(&self) -> Result<()> {
+        self.validate_url()?;
         if let Some(params) = self.params.as_ref() {
             if !params.is_object() {
                 return Err(anyhow!(
@@ -119,16 +472,120 @@ impl RequestContext {
                 ));
             }
         }
+        if let Some(proxy) = self.proxy.as_ref() {
+            if !matches!(proxy.scheme(), "http" | "https" | "socks5" | "socks5h") {
+                return Err(anyhow!(
+                    "loading config: unsupported proxy scheme {}, expected http, https or socks5",
+                    proxy.scheme()
+                ));
+            }
+        }
         Ok(())
     }
 
+    /// absolute urls must be valid, relative ones are paths starting with `/`. A url starting
+    /// with a placeholder is only known in an environment
+    fn validate_url(&self) -> Result<()> {
+        if self.url.trim_start().starts_with("{{") {
+            return Ok(());
+        }
+        let url = fill_placeholders(&self.url, "x");
+        match Url::parse(&url) {
+            Err(url::ParseError::RelativeUrlWithoutBase) if !url.starts_with('/') => Err(anyhow!(
+                "loading config: relative url {} must start with /",
+                self.url
+            )),
+            Err(url::ParseError::RelativeUrlWithoutBase) => Ok(()),
+            Err(e) => Err(anyhow!("loading config: invalid url {}: {}", self.url, e)),
+            _ => Ok(()),
+        }
+    }
+
+    /// problems `validate` doesn't catch: urls which can't be sent, a body the method
+    /// shouldn't have, a content-type the body can't be sent as and variables which are
+    /// not defined in every environment
+    pub(crate) fn lint(&self, envs: &IndexMap<String, Environment>) -> Issues {
+        let mut issues = Issues::default();
+        let parsed = Url::parse(&self.url);
+        let scheme = parsed.as_ref().map_or("http", |url| url.scheme());
+        if !matches!(scheme, "http" | "https") {
+            issues.add(
+                "url",
+                format!("unsupported scheme {}, expected http or https", scheme),
+            );
+        }
+        match parsed.err() {
+            Some(url::ParseError::RelativeUrlWithoutBase)
+                if !envs.values().any(|env| env.has_base_url()) =>
+            {
+                issues.add("url", "relative url needs an environment with a base_url")
+            }
+            _ => {}
+        }
+
+        if matches!(self.method, Method::GET | Method::HEAD) && !empty_json_value(&self.body) {
+            issues.add(
+                "body",
+                format!("{} requests should not have a body", self.method),
+            );
+        }
+        if let Some(content_type) = resolve_content_type(&self.headers) {
+            if content_type != "application/json" {
+                issues.add(
+                    "headers.content-type",
+                    format!(
+                        "unsupported content-type {}, the body is sent as application/json",
+                        content_type
+                    ),
+                );
+            }
+        }
+
+        let mut texts = vec![("url".to_string(), self.url.clone())];
+        texts.extend(self.headers.iter().filter_map(|(k, v)| {
+            let v = v.to_str().ok()?;
+            Some((format!("headers.{}", k), v.to_string()))
+        }));
+        json_strings(self.params.as_ref(), "params", &mut texts);
+        json_strings(self.body.as_ref(), "body", &mut texts);
+        for (path, text) in &texts {
+            for name in placeholders(text) {
+                let missing: Vec<_> = envs
+                    .iter()
+                    .filter(|(_, env)| !env.has_variable(name))
+                    .map(|(env, _)| env.as_str())
+                    .collect();
+                if envs.is_empty() {
+                    issues.add(
+                        path,
+                        format!("undefined variable {}, no environment is defined", name),
+                    );
+                } else if !missing.is_empty() {
+                    issues.add(
+                        path,
+                        format!(
+                            "undefined variable {} in environment {}",
+                            name,
+                            missing.join(", ")
+                        ),
+                    );
+                }
+            }
+        }
+        issues
+    }
+
     pub fn generate(&self, args: &Args) -> Result<(HeaderMap, serde_json::Value, String)> {
         let mut headers = self.headers.clone();
         let mut query = self.params.clone().unwrap_or_else(|| json!({}));
         let mut body = self.body.clone().unwrap_or_else(|| json!({}));
 
         for (k, v) in &args.headers {
-            headers.insert(HeaderName::from_str(k)?, HeaderValue::from_str(v)?);
+            let name = HeaderName::from_str(k)?;
+            match v {
+                Some(v) => headers.insert(name, HeaderValue::from_str(v)?),
+                None => headers.remove(name),
+            };
         }
         if !headers.contains_key(header::CONTENT_TYPE) {
             headers.insert(
@@ -137,15 +594,18 @@ impl RequestContext {
             );
         }
 
-        for (k, v) in &args.query {
-            query[k] = v.parse()?;
-        }
+        apply_query(&mut query, args);
         for (k, v) in &args.body {
-            body[k] = v.parse()?;
+            match v {
+                Some(v) => set_json_path(&mut body, k, v.clone())?,
+                None => {
+                    remove_json_path(&mut body, k);
+                }
+            }
         }
 
         let content_type = resolve_content_type(&headers);
-        match content_type.as_deref() {
+        match content_type {
             Some("application/json") => {
                 let body = serde_json::to_string(&body)?;
                 Ok((headers, query, body))
@@ -154,3 +614,215 @@ impl RequestContext {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use super::*;
+    use crate::{
+        cli::parse_key_val,
+        context::mock::{mock_server, response},
+        ResponseContext,
+    };
+
+    fn request(url: &str, extra: &str) -> RequestContext {
+        serde_yaml::from_str(&format!("url: {}/a\n{}", url, extra)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn send_should_retry_configured_statuses() {
+        let (url, hits) = mock_server(vec![
+            response("503 Service Unavailable", &[], ""),
+            response("200 OK", &[], "ok"),
+        ])
+        .await;
+        let req = request(
+            &url,
+            "retry:\n  count: 2\n  backoff: 1ms\n  statuses: [503]\n",
+        );
+        let res = req.send(&Args::default()).await.unwrap().into_inner();
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.url().as_str(), format!("{}/a", url));
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn send_should_give_up_after_retry_count() {
+        let (url, hits) = mock_server(vec![response("503 Service Unavailable", &[], "")]).await;
+        let req = request(
+            &url,
+            "retry:\n  count: 1\n  backoff: 1ms\n  statuses: [503]\n",
+        );
+        let res = req.send(&Args::default()).await.unwrap();
+        assert_eq!(res.into_inner().status(), 503);
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn send_should_record_redirect_chain() {
+        let (url, _) = mock_server(vec![
+            response("302 Found", &[("location", "/b")], ""),
+            response("302 Found", &[("location", "/c?x=1")], ""),
+            response("200 OK", &[], "ok"),
+        ])
+        .await;
+        let res = request(&url, "").send(&Args::default()).await.unwrap();
+        let chain: Vec<_> = res.redirects().iter().map(|u| u.path()).collect();
+        assert_eq!(chain, vec!["/b", "/c"]);
+        let text = res.resolve_text(&ResponseContext::default()).await.unwrap();
+        assert!(text.contains("redirects: /b -> /c?x=1\r\n"));
+    }
+
+    #[tokio::test]
+    async fn send_should_not_follow_redirects_with_policy_none() {
+        let (url, hits) = mock_server(vec![
+            response("302 Found", &[("location", "/b")], ""),
+            response("200 OK", &[], "ok"),
+        ])
+        .await;
+        let res = request(&url, "redirect: none\n")
+            .send(&Args::default())
+            .await
+            .unwrap();
+        assert!(res.redirects().is_empty());
+        assert_eq!(res.into_inner().status(), 302);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn send_should_go_through_proxy() {
+        let (proxy, hits) = mock_server(vec![response("200 OK", &[], "ok")]).await;
+        let req = request("http://upstream.invalid", &format!("proxy: {}\n", proxy));
+        let res = req.send(&Args::default()).await.unwrap();
+        assert_eq!(res.into_inner().status(), 200);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn send_should_use_resolve_overrides() {
+        let (url, hits) = mock_server(vec![response("200 OK", &[], "ok")]).await;
+        let port = url.rsplit(':').next().unwrap();
+        let req = request(
+            &format!("http://canary.invalid:{}", port),
+            "resolve:\n  canary.invalid: 127.0.0.1\n",
+        );
+        let res = req.send(&Args::default()).await.unwrap();
+        assert_eq!(res.into_inner().status(), 200);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn validate_should_reject_unknown_proxy_scheme() {
+        let req = request("http://localhost", "proxy: ftp://proxy.local:21\n");
+        assert!(req.validate().is_err());
+        let req = request("http://localhost", "proxy: socks5://proxy.local:1080\n");
+        assert!(req.validate().is_ok());
+    }
+
+    #[test]
+    fn validate_should_check_urls() {
+        let request = |url: &str| -> RequestContext {
+            serde_yaml::from_str(&format!("url: '{}'", url)).unwrap()
+        };
+        for url in [
+            "https://example.com/todos",
+            "/todos/{{id}}",
+            "https://{{host}}/todos",
+            "{{base}}/todos",
+        ] {
+            assert!(request(url).validate().is_ok(), "{}", url);
+        }
+        for url in ["todos/1", "http://exa mple.com/", "https://:80/"] {
+            assert!(request(url).validate().is_err(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn generate_should_apply_and_remove_overrides() {
+        let req = request(
+            "http://localhost",
+            "params:\n  page: 1\n  debug: true\nheaders:\n  x-trace: on\nbody:\n  user:\n    name: alice\n    age: 30\n",
+        );
+        let args: Args = [
+            "-debug",
+            "size=10",
+            "-%x-trace",
+            "%x-flag=on",
+            "-@user.age",
+            "@user.city=Paris",
+        ]
+        .into_iter()
+        .map(|s| parse_key_val(s).unwrap())
+        .collect::<Vec<_>>()
+        .into();
+        let (headers, query, body) = req.generate(&args).unwrap();
+        assert_eq!(query, json!({"page": 1, "size": "10"}));
+        assert!(headers.get("x-trace").is_none());
+        assert_eq!(headers.get("x-flag").unwrap(), "on");
+        assert_eq!(body, r#"{"user":{"city":"Paris","name":"alice"}}"#);
+    }
+
+    #[test]
+    fn from_str_should_keep_strings_and_collect_repeated_keys() {
+        let req: RequestContext = "https://api.com/x?q=shoes&sort=asc&page=2&debug=true&id=007&price=1.50&tag=a&tag=b&empty="
+            .parse()
+            .unwrap();
+        assert_eq!(req.raw_url(), "https://api.com/x");
+        assert_eq!(
+            req.params,
+            Some(json!({
+                "q": "shoes",
+                "sort": "asc",
+                "page": 2,
+                "debug": true,
+                "id": "007",
+                "price": "1.50",
+                "tag": ["a", "b"],
+                "empty": "",
+            }))
+        );
+
+        let req: RequestContext = "https://api.com/x?user[name]=a%20b&user[tags][]=x&user[tags][]=y&ids[0]=1&ids[1]=2&odd[=1"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            req.params,
+            Some(json!({
+                "user": {"name": "a b", "tags": ["x", "y"]},
+                "ids": [1, 2],
+                "odd[": 1,
+            }))
+        );
+    }
+
+    #[test]
+    fn from_str_should_round_trip_through_url() {
+        // params are a json object, so the keys come back sorted
+        for (url, expected) in [
+            (
+                "https://api.com/x?q=shoes+and+socks&tag=a&tag=b&n=-3&f=0.5",
+                "https://api.com/x?f=0.5&n=-3&q=shoes+and+socks&tag=a&tag=b",
+            ),
+            (
+                "https://api.com/x?user[name]=a&user[tags][]=x&user[tags][]=y&ok=false",
+                "https://api.com/x?ok=false&user[name]=a&user[tags][0]=x&user[tags][1]=y",
+            ),
+            (
+                "https://api.com/x?id[]=1&items[0][id]=a%26b&items[1][id]=c",
+                "https://api.com/x?id[0]=1&items[0][id]=a%26b&items[1][id]=c",
+            ),
+            (
+                "https://api.com/x?m[0][]=1&m[0][]=2&m[1][]=3",
+                "https://api.com/x?m[0][0]=1&m[0][1]=2&m[1][0]=3",
+            ),
+        ] {
+            let req: RequestContext = url.parse().unwrap();
+            let again = req.url(&Args::default()).unwrap();
+            assert_eq!(again, expected);
+            let again: RequestContext = again.parse().unwrap();
+            assert_eq!(again.params, req.params);
+            assert_eq!(again.raw_url(), req.raw_url());
+        }
+    }
+}
@@ -1,15 +1,29 @@
 use anyhow::Result;
+use bytes::Bytes;
+use http::{HeaderMap, HeaderName};
 use reqwest::Response;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use url::{Position, Url};
 
-use super::{body_text, headers_text, status_text};
+use super::{body_text, headers_text, load_schema, status_text, BodySchema};
+use super::{Assertions, ResponseMetrics};
+use crate::{config::Issues, utils::is_default};
 
-#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Default, PartialEq, Eq)]
 pub struct ResponseContext {
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
     skip_headers: Vec<String>,
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
     skip_body: Vec<String>,
+    #[serde(rename = "assert", skip_serializing_if = "is_default", default)]
+    assertions: Assertions,
+    /// path of a local json schema file the body must conform to
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    schema: Option<String>,
+    /// send every request twice and ignore the fields that differ between the two
+    #[serde(skip_serializing_if = "is_default", default)]
+    detect_noise: bool,
 }
 
 impl ResponseContext {
@@ -17,27 +31,141 @@ impl ResponseContext {
         Self {
             skip_headers,
             skip_body,
+            assertions: Assertions::default(),
+            schema: None,
+            detect_noise: false,
         }
     }
+
+    pub fn detect_noise(&self) -> bool {
+        self.detect_noise
+    }
+
+    /// add headers and body paths to the skipped ones
+    pub fn skip(
+        &mut self,
+        headers: impl IntoIterator<Item = String>,
+        body: impl IntoIterator<Item = String>,
+    ) {
+        self.skip_headers.extend(headers);
+        self.skip_body.extend(body);
+    }
+
+    pub fn assertions(&self) -> &Assertions {
+        &self.assertions
+    }
+
+    pub fn schema(&self) -> Option<&str> {
+        self.schema.as_deref()
+    }
+
+    /// problems of the skipped fields, `validate` checks the assertions and the schema
+    pub(crate) fn lint(&self) -> Issues {
+        let mut issues = Issues::default();
+        for (idx, name) in self.skip_headers.iter().enumerate() {
+            let name = match name.strip_prefix("set-cookie[") {
+                Some(cookie) if cookie.ends_with(']') && cookie.len() > 1 => continue,
+                _ => name,
+            };
+            if HeaderName::from_bytes(name.as_bytes()).is_err() {
+                issues.add(
+                    format!("skip_headers.{}", idx),
+                    format!("invalid header name {}", name),
+                );
+            }
+        }
+        for (idx, path) in self.skip_body.iter().enumerate() {
+            if path
+                .split('.')
+                .any(|s| s.is_empty() || s.contains(char::is_whitespace))
+            {
+                issues.add(
+                    format!("skip_body.{}", idx),
+                    format!("invalid json path {:?}", path),
+                );
+            }
+        }
+        issues
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        self.assertions.validate()?;
+        if let Some(schema) = &self.schema {
+            load_schema(schema)?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
-pub struct ResponseHandler(Response);
+pub struct ResponseHandler {
+    res: Response,
+    body: Bytes,
+    metrics: ResponseMetrics,
+    redirects: Vec<Url>,
+}
 
 impl ResponseHandler {
-    pub fn new(res: Response) -> Self {
-        Self(res)
+    /// `res` must carry the same body as `body`, which is kept for the assertions
+    pub fn new(res: Response, body: Bytes, metrics: ResponseMetrics) -> Self {
+        Self {
+            res,
+            body,
+            metrics,
+            redirects: vec![],
+        }
+    }
+
+    /// the urls the request was redirected to, in the order they were followed
+    pub fn with_redirects(mut self, redirects: Vec<Url>) -> Self {
+        self.redirects = redirects;
+        self
+    }
+
+    pub fn redirects(&self) -> &[Url] {
+        &self.redirects
+    }
+
+    /// check the response against the given assertions, return the violated ones
+    pub fn check(&self, assertions: &Assertions) -> Vec<String> {
+        assertions.check(self.res.status(), self.res.headers(), &self.body)
+    }
+
+    /// validate the body against the json schema, return the violations
+    pub fn check_schema(&self, schema: &BodySchema) -> Vec<String> {
+        schema.violations(&self.body)
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        self.res.headers()
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
     }
 
     pub fn into_inner(self) -> Response {
-        self.0
+        self.res
+    }
+
+    pub fn metrics(&self) -> ResponseMetrics {
+        self.metrics
     }
 
     pub async fn resolve_text(self, ctx: &ResponseContext) -> Result<String> {
-        let res = self.0;
+        let res = self.res;
 
         let mut output = String::new();
         output.push_str(&status_text(&res)?);
+        if !self.redirects.is_empty() {
+            // the hosts differ between the sides of a diff, so only the paths are compared
+            let chain: Vec<_> = self
+                .redirects
+                .iter()
+                .map(|u| &u[Position::BeforePath..])
+                .collect();
+            output.push_str(&format!("redirects: {}\r\n", chain.join(" -> ")));
+        }
 
         output.push_str(&headers_text(&res, &ctx.skip_headers)?);
 
@@ -46,7 +174,7 @@ impl ResponseHandler {
     }
 
     pub fn header_keys(&self) -> Vec<String> {
-        self.0
+        self.res
             .headers()
             .iter()
             .map(|(k, _)| k.as_str().to_owned())
@@ -0,0 +1,95 @@
+use anyhow::{anyhow, Context, Result};
+use jsonschema::Validator;
+use serde_json::Value;
+
+/// load a json schema from a local file and make sure it compiles
+pub fn load_schema(path: &str) -> Result<Value> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read schema file: {}", path))?;
+    let schema: Value = serde_json::from_str(&content)
+        .with_context(|| format!("failed to parse schema file: {}", path))?;
+    jsonschema::validator_for(&schema).map_err(|e| anyhow!("invalid schema {}: {}", path, e))?;
+    Ok(schema)
+}
+
+/// a json schema file compiled once to validate the bodies of an item
+pub struct BodySchema {
+    validator: Validator,
+}
+
+impl BodySchema {
+    pub fn load(path: &str) -> Result<Self> {
+        let schema = load_schema(path)?;
+        let validator = jsonschema::validator_for(&schema)
+            .map_err(|e| anyhow!("invalid schema {}: {}", path, e))?;
+        Ok(Self { validator })
+    }
+
+    /// validate a response body against the schema, return the violations
+    pub fn violations(&self, body: &[u8]) -> Vec<String> {
+        match serde_json::from_slice::<Value>(body) {
+            Ok(instance) => violations(&self.validator, &instance),
+            Err(e) => vec![format!("schema: body is not valid json: {}", e)],
+        }
+    }
+}
+
+/// every violation is reported with the json pointer of the offending instance
+pub(crate) fn schema_violations(schema: &Value, instance: &Value) -> Result<Vec<String>> {
+    let validator =
+        jsonschema::validator_for(schema).map_err(|e| anyhow!("invalid schema: {}", e))?;
+    Ok(violations(&validator, instance))
+}
+
+fn violations(validator: &Validator, instance: &Value) -> Vec<String> {
+    validator
+        .iter_errors(instance)
+        .map(|e| {
+            let path = e.instance_path.to_string();
+            let path = if path.is_empty() { "/" } else { &path };
+            format!("schema: {}: {}", path, e)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn schema_violations_should_report_instance_path() {
+        let schema = json!({
+            "type": "object",
+            "required": ["id", "title"],
+            "properties": {
+                "id": { "type": "integer" },
+                "tags": { "type": "array", "items": { "type": "string" } }
+            }
+        });
+        let valid = json!({"id": 1, "title": "foo", "tags": ["a"]});
+        assert!(schema_violations(&schema, &valid).unwrap().is_empty());
+
+        let invalid = json!({"id": "1", "tags": ["a", 2]});
+        let violations = schema_violations(&schema, &invalid).unwrap();
+        assert_eq!(violations.len(), 3);
+        assert!(violations.iter().any(|v| v.starts_with("schema: /: ")));
+        assert!(violations.iter().any(|v| v.starts_with("schema: /id: ")));
+        assert!(violations
+            .iter()
+            .any(|v| v.starts_with("schema: /tags/1: ")));
+    }
+
+    #[test]
+    fn body_schema_should_work() {
+        let schema = BodySchema::load("fixtures/todo.schema.json").unwrap();
+        let violations = schema.violations(b"not json");
+        assert_eq!(violations.len(), 1);
+        assert!(violations[0].starts_with("schema: body is not valid json"));
+
+        let body = json!({"userId": 1, "id": 1, "title": "foo", "completed": false});
+        let violations = schema.violations(body.to_string().as_bytes());
+        assert!(violations.is_empty());
+    }
+}
@@ -0,0 +1,553 @@
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use http::{HeaderMap, HeaderName, HeaderValue, Method};
+use reqwest::Request;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+use url::Url;
+
+use super::Secret;
+use crate::utils::is_default;
+
+/// signs a fully built request, it runs after headers, query and body are in place
+pub trait Signer {
+    fn sign(&self, req: &mut Request) -> Result<()>;
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SignContext {
+    Hmac(HmacSigner),
+    AwsSigv4(AwsSigV4Signer),
+}
+
+impl Signer for SignContext {
+    fn sign(&self, req: &mut Request) -> Result<()> {
+        match self {
+            SignContext::Hmac(signer) => signer.sign(req),
+            SignContext::AwsSigv4(signer) => signer.sign(req),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HmacAlgorithm {
+    #[default]
+    Sha256,
+    Sha512,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureEncoding {
+    #[default]
+    Hex,
+    Base64,
+}
+
+/// generic HMAC signature over a configurable canonical string
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+pub struct HmacSigner {
+    secret: Secret,
+    /// header the signature is written to
+    header: String,
+    /// template of the signed string, supports `{method}`, `{host}`, `{path}`, `{query}`,
+    /// `{body}`, `{timestamp}` and `{header:<name>}`
+    #[serde(default = "default_canonical")]
+    canonical: String,
+    #[serde(skip_serializing_if = "is_default", default)]
+    algorithm: HmacAlgorithm,
+    #[serde(skip_serializing_if = "is_default", default)]
+    encoding: SignatureEncoding,
+    /// prepended to the signature, e.g. `HMAC `
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    prefix: Option<String>,
+    /// if set, the unix timestamp used for `{timestamp}` is also sent in this header
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    timestamp_header: Option<String>,
+}
+
+fn default_canonical() -> String {
+    "{method}\n{path}\n{query}\n{body}".to_string()
+}
+
+impl Signer for HmacSigner {
+    fn sign(&self, req: &mut Request) -> Result<()> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        if let Some(name) = &self.timestamp_header {
+            req.headers_mut().insert(
+                HeaderName::from_bytes(name.as_bytes())?,
+                HeaderValue::from(timestamp),
+            );
+        }
+
+        let canonical = self.canonical_string(req, timestamp);
+        let signature = self.signature(canonical.as_bytes())?;
+        let value = format!(
+            "{}{}",
+            self.prefix.as_deref().unwrap_or_default(),
+            signature
+        );
+        req.headers_mut().insert(
+            HeaderName::from_bytes(self.header.as_bytes())?,
+            HeaderValue::from_str(&value)?,
+        );
+        Ok(())
+    }
+}
+
+impl HmacSigner {
+    fn canonical_string(&self, req: &Request, timestamp: u64) -> String {
+        let url = req.url();
+        let body = req
+            .body()
+            .and_then(|b| b.as_bytes())
+            .map(String::from_utf8_lossy)
+            .unwrap_or_default();
+        let mut output = self
+            .canonical
+            .replace("{method}", req.method().as_str())
+            .replace("{host}", url.host_str().unwrap_or_default())
+            .replace("{path}", url.path())
+            .replace("{query}", url.query().unwrap_or_default())
+            .replace("{timestamp}", &timestamp.to_string())
+            .replace("{body}", &body);
+        while let Some(start) = output.find("{header:") {
+            let Some(len) = output[start..].find('}') else {
+                break;
+            };
+            let name = &output[start + 8..start + len];
+            let value = req
+                .headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+            output.replace_range(start..start + len + 1, &value);
+        }
+        output
+    }
+
+    fn signature(&self, data: &[u8]) -> Result<String> {
+        let secret = self.secret.resolve()?;
+        let digest = match self.algorithm {
+            HmacAlgorithm::Sha256 => hmac_sha256(secret.as_bytes(), data),
+            HmacAlgorithm::Sha512 => hmac_sha512(secret.as_bytes(), data),
+        };
+        Ok(match self.encoding {
+            SignatureEncoding::Hex => hex::encode(digest),
+            SignatureEncoding::Base64 => STANDARD.encode(digest),
+        })
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hmac_sha512(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("hmac accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// AWS Signature Version 4
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+pub struct AwsSigV4Signer {
+    service: String,
+    region: String,
+    /// explicit credentials, otherwise they are taken from the environment or the profile file
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    credentials: Option<AwsCredentialsContext>,
+    /// profile in the shared credentials file, defaults to `AWS_PROFILE` or `default`
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    profile: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+pub struct AwsCredentialsContext {
+    access_key_id: String,
+    secret_access_key: Secret,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    session_token: Option<Secret>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct AwsCredentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+impl Signer for AwsSigV4Signer {
+    fn sign(&self, req: &mut Request) -> Result<()> {
+        let credentials = self.credentials()?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let amz_date = format_amz_date(now);
+        let payload = req.body().and_then(|b| b.as_bytes()).unwrap_or_default();
+        let payload_hash = hex::encode(Sha256::digest(payload));
+
+        let headers = req.headers_mut();
+        headers.insert("x-amz-date", HeaderValue::from_str(&amz_date)?);
+        if self.service == "s3" {
+            headers.insert(
+                "x-amz-content-sha256",
+                HeaderValue::from_str(&payload_hash)?,
+            );
+        }
+        if let Some(token) = &credentials.session_token {
+            headers.insert("x-amz-security-token", HeaderValue::from_str(token)?);
+        }
+
+        let authorization = self.authorization(
+            req.method(),
+            req.url(),
+            req.headers(),
+            &payload_hash,
+            &amz_date,
+            &credentials,
+        )?;
+        req.headers_mut().insert(
+            http::header::AUTHORIZATION,
+            HeaderValue::from_str(&authorization)?,
+        );
+        Ok(())
+    }
+}
+
+impl AwsSigV4Signer {
+    fn credentials(&self) -> Result<AwsCredentials> {
+        if let Some(credentials) = &self.credentials {
+            return Ok(AwsCredentials {
+                access_key_id: credentials.access_key_id.clone(),
+                secret_access_key: credentials.secret_access_key.resolve()?,
+                session_token: credentials
+                    .session_token
+                    .as_ref()
+                    .map(Secret::resolve)
+                    .transpose()?,
+            });
+        }
+        if self.profile.is_none() {
+            if let (Ok(access_key_id), Ok(secret_access_key)) = (
+                std::env::var("AWS_ACCESS_KEY_ID"),
+                std::env::var("AWS_SECRET_ACCESS_KEY"),
+            ) {
+                return Ok(AwsCredentials {
+                    access_key_id,
+                    secret_access_key,
+                    session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+                });
+            }
+        }
+
+        let profile = self
+            .profile
+            .clone()
+            .or_else(|| std::env::var("AWS_PROFILE").ok())
+            .unwrap_or_else(|| "default".to_string());
+        let path = std::env::var("AWS_SHARED_CREDENTIALS_FILE")
+            .or_else(|_| std::env::var("HOME").map(|home| format!("{}/.aws/credentials", home)))?;
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read aws credentials file: {}", path))?;
+        profile_credentials(&content, &profile)
+            .ok_or_else(|| anyhow!("aws profile {} not found in {}", profile, path))
+    }
+
+    fn authorization(
+        &self,
+        method: &Method,
+        url: &Url,
+        headers: &HeaderMap,
+        payload_hash: &str,
+        amz_date: &str,
+        credentials: &AwsCredentials,
+    ) -> Result<String> {
+        // the host header is added by the http client, so it has to be signed explicitly.
+        // the authorization header is replaced by the signature, so it is never signed
+        let mut canonical_headers: Vec<(String, String)> = vec![("host".to_string(), host(url)?)];
+        for (name, value) in headers {
+            if name == http::header::AUTHORIZATION {
+                continue;
+            }
+            let value = value.to_str()?.split_whitespace().collect::<Vec<_>>();
+            canonical_headers.push((name.as_str().to_string(), value.join(" ")));
+        }
+        canonical_headers.sort();
+        let signed_headers = canonical_headers
+            .iter()
+            .map(|(k, _)| k.as_str())
+            .collect::<Vec<_>>()
+            .join(";");
+
+        let mut query: Vec<_> = url
+            .query_pairs()
+            .map(|(k, v)| (aws_encode(&k), aws_encode(&v)))
+            .collect();
+        query.sort();
+        let query = query
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n\n{}\n{}",
+            method,
+            self.canonical_uri(url),
+            query,
+            canonical_headers
+                .iter()
+                .map(|(k, v)| format!("{}:{}", k, v))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            signed_headers,
+            payload_hash
+        );
+
+        let date = &amz_date[..8];
+        let scope = format!("{}/{}/{}/aws4_request", date, self.region, self.service);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let secret = format!("AWS4{}", credentials.secret_access_key);
+        let key = hmac_sha256(secret.as_bytes(), date.as_bytes());
+        let key = hmac_sha256(&key, self.region.as_bytes());
+        let key = hmac_sha256(&key, self.service.as_bytes());
+        let key = hmac_sha256(&key, b"aws4_request");
+        let signature = hex::encode(hmac_sha256(&key, string_to_sign.as_bytes()));
+
+        Ok(format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            credentials.access_key_id, scope, signed_headers, signature
+        ))
+    }
+
+    /// s3 signs the path as it is sent, the other services encode the sent path once more
+    fn canonical_uri(&self, url: &Url) -> String {
+        match self.service == "s3" {
+            true => url.path().to_string(),
+            false => url
+                .path()
+                .split('/')
+                .map(aws_encode)
+                .collect::<Vec<_>>()
+                .join("/"),
+        }
+    }
+}
+
+fn host(url: &Url) -> Result<String> {
+    let host = url.host_str().ok_or_else(|| anyhow!("url has no host"))?;
+    Ok(match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_string(),
+    })
+}
+
+/// percent-encode everything except the unreserved characters, as required by SigV4
+fn aws_encode(s: &str) -> String {
+    let mut output = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                output.push(b as char)
+            }
+            _ => output.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    output
+}
+
+/// format unix seconds as `YYYYMMDDTHHMMSSZ`
+fn format_amz_date(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+    // civil date from days since epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// read a profile from an ini style aws shared credentials file
+fn profile_credentials(content: &str, profile: &str) -> Option<AwsCredentials> {
+    let mut sections: HashMap<&str, HashMap<&str, &str>> = HashMap::new();
+    let mut current = None;
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            current = Some(name.trim());
+            continue;
+        }
+        if let (Some(section), Some((k, v))) = (current, line.split_once('=')) {
+            sections
+                .entry(section)
+                .or_default()
+                .insert(k.trim(), v.trim());
+        }
+    }
+    let section = sections.get(profile)?;
+    Some(AwsCredentials {
+        access_key_id: section.get("aws_access_key_id")?.to_string(),
+        secret_access_key: section.get("aws_secret_access_key")?.to_string(),
+        session_token: section.get("aws_session_token").map(|v| v.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use http::header;
+    use reqwest::Client;
+
+    use super::*;
+
+    #[test]
+    fn hmac_signer_should_work() {
+        // RFC 4231 test case 2
+        let signer: HmacSigner = serde_yaml::from_str(
+            "secret: Jefe\nheader: x-signature\ncanonical: what do ya want for nothing?\n",
+        )
+        .unwrap();
+        let mut req = Client::new().get("https://example.com/").build().unwrap();
+        signer.sign(&mut req).unwrap();
+        assert_eq!(
+            req.headers()["x-signature"],
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn hmac_canonical_string_should_work() {
+        let signer: HmacSigner = serde_yaml::from_str(
+            "secret: s\nheader: x-signature\ncanonical: \"{method} {host}{path}?{query} {header:x-id} {body}\"\n",
+        )
+        .unwrap();
+        let req = Client::new()
+            .post("https://example.com/todos?a=1")
+            .header("x-id", "42")
+            .body(r#"{"b":2}"#)
+            .build()
+            .unwrap();
+        assert_eq!(
+            signer.canonical_string(&req, 0),
+            r#"POST example.com/todos?a=1 42 {"b":2}"#
+        );
+    }
+
+    #[test]
+    fn aws_sigv4_should_match_reference_signature() {
+        // example from the AWS Signature Version 4 documentation
+        let signer = AwsSigV4Signer {
+            service: "iam".to_string(),
+            region: "us-east-1".to_string(),
+            credentials: None,
+            profile: None,
+        };
+        let credentials = AwsCredentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            session_token: None,
+        };
+        let url =
+            Url::parse("https://iam.amazonaws.com/?Action=ListUsers&Version=2010-05-08").unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/x-www-form-urlencoded; charset=utf-8"),
+        );
+        headers.insert("x-amz-date", HeaderValue::from_static("20150830T123600Z"));
+        // set by `auth`, it is replaced by the signature and must not be signed
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer token"),
+        );
+        let payload_hash = hex::encode(Sha256::digest(b""));
+
+        let authorization = signer
+            .authorization(
+                &Method::GET,
+                &url,
+                &headers,
+                &payload_hash,
+                "20150830T123600Z",
+                &credentials,
+            )
+            .unwrap();
+        assert_eq!(
+            authorization,
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/iam/aws4_request, \
+             SignedHeaders=content-type;host;x-amz-date, \
+             Signature=5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7"
+        );
+    }
+
+    #[test]
+    fn aws_canonical_uri_should_encode_twice_except_for_s3() {
+        let mut signer = AwsSigV4Signer {
+            service: "execute-api".to_string(),
+            region: "us-east-1".to_string(),
+            credentials: None,
+            profile: None,
+        };
+        let url = Url::parse("https://example.com/documents and settings/a=b").unwrap();
+        assert_eq!(url.path(), "/documents%20and%20settings/a=b");
+        assert_eq!(
+            signer.canonical_uri(&url),
+            "/documents%2520and%2520settings/a%3Db"
+        );
+        signer.service = "s3".to_string();
+        assert_eq!(
+            signer.canonical_uri(&url),
+            "/documents%20and%20settings/a=b"
+        );
+    }
+
+    #[test]
+    fn format_amz_date_should_work() {
+        assert_eq!(format_amz_date(0), "19700101T000000Z");
+        assert_eq!(format_amz_date(1440938160), "20150830T123600Z");
+        assert_eq!(format_amz_date(951782400), "20000229T000000Z");
+    }
+
+    #[test]
+    fn profile_credentials_should_work() {
+        let content = "[default]\naws_access_key_id = AKID1\naws_secret_access_key = s1\n\n\
+                       [staging]\naws_access_key_id=AKID2\naws_secret_access_key=s2\naws_session_token=t2\n";
+        let credentials = profile_credentials(content, "staging").unwrap();
+        assert_eq!(credentials.access_key_id, "AKID2");
+        assert_eq!(credentials.secret_access_key, "s2");
+        assert_eq!(credentials.session_token.as_deref(), Some("t2"));
+        assert!(profile_credentials(content, "missing").is_none());
+    }
+}
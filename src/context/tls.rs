@@ -0,0 +1,347 @@
+use std::{
+    fs,
+    net::{IpAddr, SocketAddr},
+};
+
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use indexmap::IndexMap;
+use p12_keystore::KeyStore;
+use reqwest::{Certificate, ClientBuilder, Identity};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::net::lookup_host;
+use url::{Host, Url};
+
+use super::Secret;
+use crate::utils::is_default;
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Default, PartialEq, Eq)]
+pub struct TlsContext {
+    /// PEM files with root certificates trusted in addition to the built-in ones
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    ca: Vec<String>,
+    /// client certificate presented for mTLS
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    identity: Option<IdentityContext>,
+    /// name used for SNI, certificate verification and the host header, the request
+    /// still goes to the address of the url host
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    server_name: Option<String>,
+    /// accept invalid certificates, only meant for self-signed test environments
+    #[serde(skip_serializing_if = "is_default", default)]
+    insecure: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum IdentityContext {
+    /// PEM certificate chain and private key, both may live in the same file
+    Pem { cert: String, key: String },
+    /// PKCS#12 archive with the private key and its certificate chain
+    Pkcs12 {
+        pkcs12: String,
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        password: Option<Secret>,
+    },
+}
+
+impl TlsContext {
+    /// configure trusted roots, client identity and server name resolution for `url`. The
+    /// server name goes to the `resolve` address of the url host if there is one, and is
+    /// left to the proxy if the request is proxied
+    pub async fn apply(
+        &self,
+        mut builder: ClientBuilder,
+        url: &Url,
+        resolve: &IndexMap<String, IpAddr>,
+        proxied: bool,
+    ) -> Result<ClientBuilder> {
+        for path in &self.ca {
+            let pem = fs::read(path).with_context(|| format!("failed to read CA file {}", path))?;
+            let certs = Certificate::from_pem_bundle(&pem)
+                .with_context(|| format!("invalid CA file {}", path))?;
+            for cert in certs {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+        if let Some(identity) = &self.identity {
+            builder = builder.identity(identity.load()?);
+        }
+        if self.insecure {
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+        if let Some(name) = self.server_name.as_ref().filter(|_| !proxied) {
+            let port = url.port_or_known_default().unwrap_or(443);
+            let addr = match url.host() {
+                Some(Host::Ipv4(ip)) => SocketAddr::new(ip.into(), port),
+                Some(Host::Ipv6(ip)) => SocketAddr::new(ip.into(), port),
+                Some(Host::Domain(host)) => match resolve.get(host) {
+                    Some(ip) => SocketAddr::new(*ip, port),
+                    None => lookup_host((host, port))
+                        .await?
+                        .next()
+                        .ok_or_else(|| anyhow!("failed to resolve {}", host))?,
+                },
+                None => return Err(anyhow!("url {} has no host", url)),
+            };
+            builder = builder.resolve(name, addr);
+        }
+        Ok(builder)
+    }
+
+    /// the url to request, with the host replaced by `server_name` if set
+    pub fn url(&self, url: &Url) -> Result<Url> {
+        let mut url = url.clone();
+        if let Some(name) = &self.server_name {
+            url.set_host(Some(name))
+                .with_context(|| format!("invalid server name {}", name))?;
+        }
+        Ok(url)
+    }
+}
+
+impl IdentityContext {
+    pub fn load(&self) -> Result<Identity> {
+        match self {
+            IdentityContext::Pem { cert, key } => {
+                let mut pem =
+                    fs::read(cert).with_context(|| format!("failed to read cert {}", cert))?;
+                if key != cert {
+                    pem.push(b'\n');
+                    pem.extend(
+                        fs::read(key).with_context(|| format!("failed to read key {}", key))?,
+                    );
+                }
+                Identity::from_pem(&pem).with_context(|| format!("invalid identity {}", cert))
+            }
+            IdentityContext::Pkcs12 { pkcs12, password } => {
+                let der = fs::read(pkcs12)
+                    .with_context(|| format!("failed to read PKCS#12 file {}", pkcs12))?;
+                let password = match password {
+                    Some(password) => password.resolve()?,
+                    None => String::new(),
+                };
+                let store = KeyStore::from_pkcs12(&der, &password)
+                    .map_err(|e| anyhow!("invalid PKCS#12 file {}: {}", pkcs12, e))?;
+                let (_, chain) = store
+                    .private_key_chain()
+                    .ok_or_else(|| anyhow!("no private key in PKCS#12 file {}", pkcs12))?;
+                // the rustls backend only takes PEM, so re-encode the chain and the PKCS#8 key
+                let mut pem = String::new();
+                for cert in chain.chain() {
+                    pem.push_str(&pem_block("CERTIFICATE", cert.as_der()));
+                }
+                pem.push_str(&pem_block("PRIVATE KEY", chain.key()));
+                Ok(Identity::from_pem(pem.as_bytes())?)
+            }
+        }
+    }
+}
+
+fn pem_block(label: &str, der: &[u8]) -> String {
+    let encoded = STANDARD.encode(der);
+    let mut output = format!("-----BEGIN {}-----\n", label);
+    for line in encoded.as_bytes().chunks(64) {
+        output.push_str(std::str::from_utf8(line).unwrap());
+        output.push('\n');
+    }
+    output.push_str(&format!("-----END {}-----\n", label));
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, sync::Arc};
+
+    use p12_keystore::{KeyStoreEntry, PrivateKeyChain};
+    use rcgen::{BasicConstraints, Certificate as RcCert, CertificateParams, IsCa};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+    use tokio_rustls::{
+        rustls::{self, server::AllowAnyAuthenticatedClient, RootCertStore},
+        TlsAcceptor,
+    };
+
+    use super::*;
+
+    struct Pki {
+        dir: PathBuf,
+        ca: RcCert,
+        server: RcCert,
+        client: RcCert,
+    }
+
+    impl Pki {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("xdiff-tls-{}-{}", name, std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            let mut params = CertificateParams::new(vec![]);
+            params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            let ca = RcCert::from_params(params).unwrap();
+            let server =
+                RcCert::from_params(CertificateParams::new(vec!["api.internal".into()])).unwrap();
+            let client =
+                RcCert::from_params(CertificateParams::new(vec!["client".into()])).unwrap();
+            let pki = Self {
+                dir,
+                ca,
+                server,
+                client,
+            };
+            pki.write("ca.pem", pki.ca.serialize_pem().unwrap().as_bytes());
+            pki.write(
+                "client.pem",
+                pki.client
+                    .serialize_pem_with_signer(&pki.ca)
+                    .unwrap()
+                    .as_bytes(),
+            );
+            pki.write(
+                "client.key",
+                pki.client.serialize_private_key_pem().as_bytes(),
+            );
+            pki
+        }
+
+        fn write(&self, name: &str, content: &[u8]) {
+            fs::write(self.dir.join(name), content).unwrap();
+        }
+
+        fn path(&self, name: &str) -> String {
+            self.dir.join(name).to_string_lossy().into_owned()
+        }
+
+        /// serve a single https response, requiring a client certificate if `mtls`
+        async fn serve(&self, mtls: bool) -> Url {
+            let cert =
+                rustls::Certificate(self.server.serialize_der_with_signer(&self.ca).unwrap());
+            let key = rustls::PrivateKey(self.server.serialize_private_key_der());
+            let builder = rustls::ServerConfig::builder().with_safe_defaults();
+            let config = if mtls {
+                let mut roots = RootCertStore::empty();
+                roots
+                    .add(&rustls::Certificate(self.ca.serialize_der().unwrap()))
+                    .unwrap();
+                builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots).boxed())
+            } else {
+                builder.with_no_client_auth()
+            }
+            .with_single_cert(vec![cert], key)
+            .unwrap();
+            let acceptor = TlsAcceptor::from(Arc::new(config));
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("https://{}/", listener.local_addr().unwrap());
+            tokio::spawn(async move {
+                loop {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    let Ok(mut stream) = acceptor.accept(stream).await else {
+                        continue;
+                    };
+                    let mut buf = vec![0; 8192];
+                    let _ = stream.read(&mut buf).await;
+                    let _ = stream
+                        .write_all(
+                            b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok",
+                        )
+                        .await;
+                    let _ = stream.shutdown().await;
+                }
+            });
+            url.parse().unwrap()
+        }
+    }
+
+    impl Drop for Pki {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    async fn get(tls: &TlsContext, url: &Url) -> Result<u16> {
+        get_resolved(tls, url, &IndexMap::new()).await
+    }
+
+    async fn get_resolved(
+        tls: &TlsContext,
+        url: &Url,
+        resolve: &IndexMap<String, IpAddr>,
+    ) -> Result<u16> {
+        let builder = tls.apply(reqwest::Client::builder(), url, resolve, false);
+        let client = builder.await?.build()?;
+        let res = client.get(tls.url(url)?).send().await?;
+        Ok(res.status().as_u16())
+    }
+
+    fn tls(yaml: &str) -> TlsContext {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[tokio::test]
+    async fn tls_should_trust_custom_ca_with_server_name() {
+        let pki = Pki::new("ca");
+        let url = pki.serve(false).await;
+        let trusted = tls(&format!(
+            "ca: [{}]\nserver_name: api.internal\n",
+            pki.path("ca.pem")
+        ));
+        assert_eq!(get(&trusted, &url).await.unwrap(), 200);
+        let untrusted = tls("server_name: api.internal\n");
+        assert!(get(&untrusted, &url).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn tls_server_name_should_use_resolve_overrides() {
+        let pki = Pki::new("resolve");
+        let mut url = pki.serve(false).await;
+        url.set_host(Some("backend.invalid")).unwrap();
+        let trusted = tls(&format!(
+            "ca: [{}]\nserver_name: api.internal\n",
+            pki.path("ca.pem")
+        ));
+        assert!(get(&trusted, &url).await.is_err());
+        let resolve = [("backend.invalid".to_string(), IpAddr::from([127, 0, 0, 1]))].into();
+        assert_eq!(get_resolved(&trusted, &url, &resolve).await.unwrap(), 200);
+    }
+
+    #[tokio::test]
+    async fn tls_insecure_should_accept_unknown_certificates() {
+        let pki = Pki::new("insecure");
+        let url = pki.serve(false).await;
+        assert_eq!(get(&tls("insecure: true\n"), &url).await.unwrap(), 200);
+    }
+
+    #[tokio::test]
+    async fn tls_should_present_client_certificate() {
+        let pki = Pki::new("mtls");
+        let url = pki.serve(true).await;
+        let base = format!("ca: [{}]\nserver_name: api.internal\n", pki.path("ca.pem"));
+        assert!(get(&tls(&base), &url).await.is_err());
+
+        let pem = tls(&format!(
+            "{}identity:\n  cert: {}\n  key: {}\n",
+            base,
+            pki.path("client.pem"),
+            pki.path("client.key")
+        ));
+        assert_eq!(get(&pem, &url).await.unwrap(), 200);
+
+        let mut store = KeyStore::new();
+        let cert = p12_keystore::Certificate::from_der(
+            &pki.client.serialize_der_with_signer(&pki.ca).unwrap(),
+        )
+        .unwrap();
+        let chain = PrivateKeyChain::new(pki.client.serialize_private_key_der(), [1], [cert]);
+        store.add_entry("client", KeyStoreEntry::PrivateKeyChain(chain));
+        pki.write("client.p12", &store.writer("secret").write().unwrap());
+        let pkcs12 = tls(&format!(
+            "{}identity:\n  pkcs12: {}\n  password: secret\n",
+            base,
+            pki.path("client.p12")
+        ));
+        assert_eq!(get(&pkcs12, &url).await.unwrap(), 200);
+    }
+}
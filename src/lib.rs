@@ -2,39 +2,82 @@ pub mod cli;
 mod config;
 mod context;
 mod utils;
-use cli::{KeyVal, KeyValType};
+use cli::{KeyVal, KeyValSide, KeyValType};
 
-pub use config::xdiff::{DiffConfig, DiffItem};
-pub use config::xreq::RequestConfig;
-pub use config::Load;
-pub use context::{body_text, headers_text, status_text};
-pub use context::{RequestContext, ResponseContext};
+pub use config::xdiff::{DiffConfig, DiffItem, DiffResult};
+pub use config::xreq::{RequestConfig, RequestItem};
+pub use config::{ConfigEditor, ConfigFormat, ItemFilter, Load, Problem};
+pub use context::Noise;
+pub use context::{body_text, headers_text, set_cookie_text, status_text};
+pub use context::{
+    metrics_text, Assertions, AuthContext, BodySchema, JsonAssertion, OAuth2Context, PerfContext,
+    RequestContext, ResponseContext, ResponseMetrics, Secret,
+};
+pub use context::{
+    AwsCredentialsContext, AwsSigV4Signer, HmacAlgorithm, HmacSigner, SignContext,
+    SignatureEncoding, Signer,
+};
+pub use context::{Cookie, CookieJar, Environment, Session};
+pub use context::{IdentityContext, TlsContext};
+pub use context::{RedirectPolicy, RetryContext, TimeoutContext};
 pub use utils::{build_diff, highlight_text};
 
+/// overrides of a single request, a `None` value removes the key
 #[derive(Debug, Default, Clone)]
 pub struct Args {
-    pub query: Vec<(String, String)>,
-    pub headers: Vec<(String, String)>,
-    pub body: Vec<(String, String)>,
+    pub query: Vec<(String, Option<serde_json::Value>)>,
+    pub headers: Vec<(String, Option<String>)>,
+    /// keys are dot separated paths, a trailing `[]` appends to an array
+    pub body: Vec<(String, Option<serde_json::Value>)>,
 }
 
+impl Args {
+    fn push(&mut self, arg: KeyVal) {
+        match arg.key_type {
+            KeyValType::Query => self.query.push((arg.key, arg.value)),
+            KeyValType::Header => {
+                let value = arg.value.map(|v| match v {
+                    serde_json::Value::String(s) => s,
+                    v => v.to_string(),
+                });
+                self.headers.push((arg.key, value))
+            }
+            KeyValType::Body => self.body.push((arg.key, arg.value)),
+        }
+    }
+}
+
+/// the side of an override is ignored for a single request
 impl From<Vec<KeyVal>> for Args {
     fn from(args: Vec<KeyVal>) -> Self {
-        let mut query = vec![];
-        let mut headers = vec![];
-        let mut body = vec![];
+        let mut result = Self::default();
+        for arg in args {
+            result.push(arg);
+        }
+        result
+    }
+}
 
+/// overrides of a diff, `left` applies to the baseline request and `right` to the others
+#[derive(Debug, Default, Clone)]
+pub struct DiffArgs {
+    pub left: Args,
+    pub right: Args,
+}
+
+impl From<Vec<KeyVal>> for DiffArgs {
+    fn from(args: Vec<KeyVal>) -> Self {
+        let mut result = Self::default();
         for arg in args {
-            match arg.key_type {
-                KeyValType::Query => query.push((arg.key, arg.value)),
-                KeyValType::Header => headers.push((arg.key, arg.value)),
-                KeyValType::Body => body.push((arg.key, arg.value)),
+            match arg.side {
+                KeyValSide::Left => result.left.push(arg),
+                KeyValSide::Right => result.right.push(arg),
+                KeyValSide::Both => {
+                    result.left.push(arg.clone());
+                    result.right.push(arg);
+                }
             }
         }
-        Self {
-            query,
-            headers,
-            body,
-        }
+        result
     }
 }
@@ -1,6 +1,6 @@
 use std::fmt::{Display, Formatter};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use console::{style, Style};
 use similar::{ChangeTag, TextDiff};
 use std::io::Write;
@@ -21,6 +21,102 @@ impl Display for Line {
     }
 }
 
+pub(crate) fn is_default<T: Default + PartialEq>(v: &T) -> bool {
+    v == &T::default()
+}
+
+/// look up a dot separated path like `data.items.0.id` in a json value
+pub(crate) fn get_json_path<'a>(
+    value: &'a serde_json::Value,
+    path: &str,
+) -> Option<&'a serde_json::Value> {
+    let path = path.strip_prefix("$.").unwrap_or(path);
+    if path.is_empty() || path == "$" {
+        return Some(value);
+    }
+    path.split('.').try_fold(value, |value, key| match value {
+        serde_json::Value::Object(obj) => obj.get(key),
+        serde_json::Value::Array(arr) => key.parse::<usize>().ok().and_then(|i| arr.get(i)),
+        _ => None,
+    })
+}
+
+/// set the value at a dot separated path like `user.address.city`, missing objects are
+/// created on the way. A trailing `[]` like `tags[]` appends to the array at the path
+pub(crate) fn set_json_path(
+    target: &mut serde_json::Value,
+    path: &str,
+    value: serde_json::Value,
+) -> Result<()> {
+    let (path, append) = match path.strip_suffix("[]") {
+        Some(path) => (path, true),
+        None => (path, false),
+    };
+    let mut current = target;
+    for key in path.split('.') {
+        current = match current {
+            serde_json::Value::Array(arr) => {
+                let idx: usize = key
+                    .parse()
+                    .map_err(|_| anyhow!("{} is not an index of an array in {}", key, path))?;
+                arr.get_mut(idx)
+                    .ok_or_else(|| anyhow!("index {} out of range in {}", idx, path))?
+            }
+            v => {
+                if !v.is_object() {
+                    *v = serde_json::Value::Object(Default::default());
+                }
+                v.as_object_mut()
+                    .unwrap()
+                    .entry(key)
+                    .or_insert(serde_json::Value::Null)
+            }
+        };
+    }
+    if !append {
+        *current = value;
+        return Ok(());
+    }
+    match current {
+        serde_json::Value::Array(arr) => arr.push(value),
+        serde_json::Value::Null => *current = serde_json::Value::Array(vec![value]),
+        v => *v = serde_json::Value::Array(vec![v.take(), value]),
+    }
+    Ok(())
+}
+
+/// remove the value at a dot separated path like `data.items.0.id`, array elements are
+/// replaced by null to keep the indices of the following elements stable
+pub(crate) fn remove_json_path(value: &mut serde_json::Value, path: &str) -> bool {
+    let path = path.strip_prefix("$.").unwrap_or(path);
+    let (parent, key) = match path.rsplit_once('.') {
+        Some((parent, key)) => {
+            let parent = parent.split('.').try_fold(value, |value, key| match value {
+                serde_json::Value::Object(obj) => obj.get_mut(key),
+                serde_json::Value::Array(arr) => {
+                    key.parse::<usize>().ok().and_then(|i| arr.get_mut(i))
+                }
+                _ => None,
+            });
+            (parent, key)
+        }
+        None => (Some(value), path),
+    };
+    match parent {
+        Some(serde_json::Value::Object(obj)) => obj.remove(key).is_some(),
+        Some(serde_json::Value::Array(arr)) => {
+            match key.parse::<usize>().ok().and_then(|i| arr.get_mut(i)) {
+                Some(v) => {
+                    *v = serde_json::Value::Null;
+                    true
+                }
+                None => false,
+            }
+        }
+        _ => false,
+    }
+}
+
 pub fn build_diff(old: String, new: String) -> Result<String> {
     let diff = TextDiff::from_lines(&old, &new);
     let mut buf = Vec::with_capacity(4096);
@@ -64,7 +160,9 @@ pub fn highlight_text(text: &str, extension: &str) -> Result<String> {
     let ps = SyntaxSet::load_defaults_newlines();
     let ts = ThemeSet::load_defaults();
 
-    let syntax = ps.find_syntax_by_extension(extension).unwrap();
+    let syntax = ps
+        .find_syntax_by_extension(extension)
+        .unwrap_or_else(|| ps.find_syntax_plain_text());
     let mut h = HighlightLines::new(syntax, &ts.themes["base16-ocean.dark"]);
 
     let mut output = String::new();
@@ -91,6 +189,48 @@ mod tests {
         assert_eq!(diff, expected);
     }
 
+    #[test]
+    fn get_json_path_should_work() {
+        let value = json!({"data": {"items": [{"id": 1}, {"id": 2}]}});
+        assert_eq!(get_json_path(&value, "data.items.1.id"), Some(&json!(2)));
+        assert_eq!(
+            get_json_path(&value, "$.data.items.0"),
+            Some(&json!({"id": 1}))
+        );
+        assert_eq!(get_json_path(&value, "$"), Some(&value));
+        assert_eq!(get_json_path(&value, "data.items.2.id"), None);
+        assert_eq!(get_json_path(&value, "data.missing"), None);
+    }
+
+    #[test]
+    fn set_json_path_should_work() {
+        let mut value = json!({"user": {"name": "alice"}, "tags": ["a"], "items": [{"id": 1}]});
+        set_json_path(&mut value, "user.address.city", json!("Paris")).unwrap();
+        set_json_path(&mut value, "tags[]", json!("b")).unwrap();
+        set_json_path(&mut value, "ids[]", json!(1)).unwrap();
+        set_json_path(&mut value, "items.0.id", json!(2)).unwrap();
+        assert!(set_json_path(&mut value, "items.1.id", json!(3)).is_err());
+        assert_eq!(
+            value,
+            json!({
+                "user": {"name": "alice", "address": {"city": "Paris"}},
+                "tags": ["a", "b"],
+                "ids": [1],
+                "items": [{"id": 2}]
+            })
+        );
+    }
+
+    #[test]
+    fn remove_json_path_should_work() {
+        let mut value = json!({"id": 1, "data": {"items": [{"id": 1, "ts": 2}, {"id": 2}]}});
+        assert!(remove_json_path(&mut value, "data.items.0.ts"));
+        assert!(remove_json_path(&mut value, "$.data.items.1"));
+        assert!(remove_json_path(&mut value, "id"));
+        assert!(!remove_json_path(&mut value, "data.missing.id"));
+        assert_eq!(value, json!({"data": {"items": [{"id": 1}, null]}}));
+    }
+
     #[test]
     fn highlight_text_should_work() {
         let source = json!({